@@ -0,0 +1,58 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Serialise an arbitrary command, optionally corrupt one byte of the
+//! packet, then feed it through the parser one byte at a time.
+//!
+//! Run with `cargo +nightly fuzz run parser` from the `dfr0299`
+//! directory.
+
+#![no_main]
+
+use dfr0299::{Command, Error, ParseResult, Parser, RequestAck};
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input {
+    cmd: Command,
+    ack: RequestAck,
+    /// Index of the byte to corrupt and the mask to XOR it with
+    corrupt: Option<(u8, u8)>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut buf = [0u8; 10];
+    let len = input.cmd.serialise_with_ack(&mut buf, input.ack).unwrap();
+    assert_eq!(len, buf.len());
+
+    let corrupted = match input.corrupt {
+        Some((idx, mask)) if mask != 0 => {
+            buf[usize::from(idx) % buf.len()] ^= mask;
+            true
+        }
+        _ => false,
+    };
+
+    let mut parser = Parser::new();
+    let mut completed = false;
+    for byte in buf {
+        match parser.process_byte(byte) {
+            Ok(ParseResult::Incomplete) => {}
+            Ok(ParseResult::Ignored(_)) => {
+                assert!(corrupted, "byte ignored in valid packet {buf:02x?}")
+            }
+            Ok(ParseResult::Complete(_)) => completed = true,
+            Err(Error::BadChecksum { .. }) => {
+                assert!(corrupted, "bad checksum on valid packet {buf:02x?}")
+            }
+            // Most command bytes are not valid response codes, and the
+            // parameters of those that are may not be valid either
+            Err(Error::InvalidCommand(_) | Error::InvalidParameterValue(_)) => {}
+            Err(e) => panic!("unexpected error {e:?} for {buf:02x?}"),
+        }
+    }
+
+    // a single corrupted byte must never yield a complete message
+    assert!(!(corrupted && completed), "corruption missed in {buf:02x?}");
+});
@@ -0,0 +1,87 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Constants for the commands which have no parameter, for use in
+//! `static` tables and other const contexts.
+//!
+//! ## Example
+//! ```
+//! use dfr0299::commands::*;
+//! use dfr0299::Command;
+//! static BUTTONS: [Command; 3] = [CMD_PREVIOUS, CMD_PAUSE, CMD_NEXT];
+//! assert_eq!(BUTTONS[1], Command::Pause);
+//! ```
+
+use crate::Command;
+
+/// `Command::Next`
+pub const CMD_NEXT: Command = Command::Next;
+/// `Command::Previous`
+pub const CMD_PREVIOUS: Command = Command::Previous;
+/// `Command::IncreaseVolume`
+pub const CMD_INCREASE_VOLUME: Command = Command::IncreaseVolume;
+/// `Command::DecreaseVolume`
+pub const CMD_DECREASE_VOLUME: Command = Command::DecreaseVolume;
+/// `Command::Standby`
+pub const CMD_STANDBY: Command = Command::Standby;
+/// `Command::Wake`
+pub const CMD_WAKE: Command = Command::Wake;
+/// `Command::Reset`
+pub const CMD_RESET: Command = Command::Reset;
+/// `Command::Playback`
+pub const CMD_PLAYBACK: Command = Command::Playback;
+/// `Command::Pause`
+pub const CMD_PAUSE: Command = Command::Pause;
+/// `Command::StopAdvertise`
+pub const CMD_STOP_ADVERTISE: Command = Command::StopAdvertise;
+/// `Command::Stop`
+pub const CMD_STOP: Command = Command::Stop;
+/// `Command::RandomPlay`
+pub const CMD_RANDOM_PLAY: Command = Command::RandomPlay;
+/// `Command::GetStatus`
+pub const CMD_GET_STATUS: Command = Command::GetStatus;
+/// `Command::GetVolume`
+pub const CMD_GET_VOLUME: Command = Command::GetVolume;
+
+/// Reset the device, then start and immediately pause playback so that
+/// the first track is loaded ready to play
+pub const DEFAULT_STARTUP: [Command; 3] = [CMD_RESET, CMD_PLAYBACK, CMD_PAUSE];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    static STARTUP: &[Command] = &DEFAULT_STARTUP;
+
+    struct Buttons {
+        short_press: Command,
+        long_press: Command,
+    }
+
+    impl Buttons {
+        const DEFAULT: Self = Self {
+            short_press: CMD_PLAYBACK,
+            long_press: CMD_STOP,
+        };
+    }
+
+    const fn is_stop(cmd: Command) -> bool {
+        matches!(cmd, CMD_STOP)
+    }
+
+    // evaluated at compile time
+    const _: () = assert!(is_stop(Buttons::DEFAULT.long_press));
+
+    #[test]
+    fn const_context() {
+        assert_eq!(
+            STARTUP,
+            [Command::Reset, Command::Playback, Command::Pause]
+        );
+        assert_eq!(Buttons::DEFAULT.short_press, Command::Playback);
+        assert!(!is_stop(CMD_NEXT));
+        assert!(DEFAULT_STARTUP.iter().all(|cmd| !cmd.has_parameter()));
+    }
+}
@@ -4,11 +4,17 @@
 
 //! Definitions for Command and Control packet types.
 
-use crate::{Error, Result, START, STOP, VERSION};
+use crate::proto::*;
+use crate::{calculate_checksum, Disk, Error, FolderTrack, Packet, Result};
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+use num_enum::TryFromPrimitive;
 
 /// Available commands supported by the DFR0299
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Command {
     // "Control" messages
     /// Advance to the next track (loops back to the first track if it
@@ -37,7 +43,8 @@ pub enum Command {
     SetPlaybackSource(PlaybackSource),
     /// Enter standby mode. This does not cause the controller to enter
     /// any kind of sleep state, instead it disables the playback
-    /// function
+    /// function. Use `Stop` to stop the current track without
+    /// disabling playback.
     /// <https://github.com/DFRobot/DFPlayer-Mini-mp3/issues/2>
     Standby,
     /// Wake from sleep (i.e. enter "normal" mode)
@@ -46,20 +53,25 @@ pub enum Command {
     Reset,
     /// Playback mode (I think this is as opposed to 'Pause')
     Playback,
-    /// Pause current track
+    /// Pause the current track. The playback position is retained, so
+    /// sending `Playback` resumes from the same point
     Pause,
     /// Play the specified track from the given folder. Note that the
     /// folder name MUST be two ascii digits and the file name MUST be
     /// four ascii digits. For example `SetFolder { folder: 4, file: 123 }`
-    /// refers to the file named '0123.mp3' in the folder '04'.
+    /// refers to the file named '0123.mp3' in the folder '04'. The
+    /// file name is zero-padded even though only files 1-255 can be
+    /// addressed. Use `Command::try_set_folder` to check the ranges.
     SetFolder {
-        /// Folder name (0-2999)
+        /// Folder name (1-99)
         folder: u8,
-        /// File name
+        /// File name (1-255)
         file: u8,
     },
     /// Set some sort of gain parameter. According to the datasheet,
-    /// `gain` should be in the range 0-31, but this is not checked here
+    /// `gain` should be in the range 0-31, but this is not checked here.
+    /// Use `Command::try_set_volume_adjust` or `VolumeAdjust` to check
+    /// the range.
     SetVolumeAdjust {
         /// Enable this gain parameter
         enable: bool,
@@ -68,6 +80,57 @@ pub enum Command {
     },
     /// `true` to start repeat play, `false` to stop repeat play
     RepeatPlay(bool),
+    /// Interrupt the current track to play the specified track from the
+    /// `ADVERT` folder, then resume the interrupted track. Advert files
+    /// should be named with four ascii digits, e.g. `ADVERT/0001.mp3`.
+    /// This only works while a track is already playing. The device
+    /// does not send a notification when the advert has finished, but
+    /// it can be cancelled early with `StopAdvertise`.
+    AdvertiseTrack(u16),
+    /// Interrupt the current track to play the specified track from the
+    /// given folder as an announcement, then resume the interrupted
+    /// track. As with `SetFolder`, the folder name MUST be two ascii
+    /// digits and the file name MUST be four ascii digits, so
+    /// `AdvertiseFolder { folder: 2, track: 5 }` refers to the file
+    /// named '0005.mp3' in the folder '02'.
+    AdvertiseFolder {
+        /// Folder name
+        folder: u8,
+        /// File name
+        track: u8,
+    },
+    /// Stop an advert started by `AdvertiseTrack` or `AdvertiseFolder`
+    /// and immediately resume the interrupted track. If no advert is
+    /// playing then this has no effect on playback, although the device
+    /// may respond with a `Response::ModuleError`.
+    StopAdvertise,
+    /// Stop playing the current track. Unlike `Pause` the playback
+    /// position is not retained, and unlike `Standby` the playback
+    /// function remains enabled so a new track can be played straight
+    /// away.
+    Stop,
+    /// Start looping all of the tracks in the specified folder. This
+    /// takes effect immediately in a single packet, whereas sending
+    /// `SetPlaybackMode(PlaybackMode::FolderRepeat)` followed by
+    /// `SetFolder` changes the repeat mode and then starts a track.
+    LoopFolder(u8),
+    /// Immediately start playing all tracks in a random order. This is
+    /// distinct from `SetPlaybackMode(PlaybackMode::Random)`, which just
+    /// sets the mode used for subsequent playback and doesn't start
+    /// playing anything by itself.
+    RandomPlay,
+    /// `true` to start looping the current track, `false` to stop.
+    /// Note that the datasheet specifies the parameter the opposite way
+    /// round to what you might expect: `0` enables looping and `1`
+    /// disables it. This crate handles the inversion, so `true` is
+    /// sent as `0`.
+    LoopCurrentTrack(bool),
+    /// `true` to enable the DAC output, `false` to disable it and
+    /// save power. As with `LoopCurrentTrack` the parameter is inverted
+    /// on the wire, so `true` is sent as `0`. Disabling the DAC only
+    /// silences the audio output; the module otherwise carries on as
+    /// normal, unlike `Standby` which disables playback entirely.
+    EnableDac(bool),
 
     // "Command" messages
     /// Datasheet just says "STAY"
@@ -82,11 +145,12 @@ pub enum Command {
     InitialisationParameters(u16),
     /// Datasheet says "returns an error, request retransmission"
     RequestRetransmission,
-    /// Datasheet says "reply". This is the command number used by 'ACK'
-    /// responses, but I'm not sure whether that's relevant here.
-    /// Perhaps the DFR02999 will sometimes request an ACK from us?
+    /// Datasheet says "reply". This is the command number (0x41) used
+    /// by the device for its ACK responses, which are parsed as
+    /// `Response::Ack`. There's no known reason for the host to send it,
+    /// but it is kept so the full command table can be represented.
     Reply,
-    /// Query the current status
+    /// Query the current status (command 0x42)
     GetStatus,
     /// Query the current volume
     GetVolume,
@@ -96,26 +160,101 @@ pub enum Command {
     GetPlaybackMode,
     /// Query the current software version
     GetSoftwareVersion,
-    /// Count the number of files on the attached SD card
-    GetTfFileCount,
-    /// Count the number of files on the attached UDisk
-    GetUDiskFileCount,
-    /// Count the number of files on the attached flash chip
-    GetFlashFileCount,
     /// Datasheet just says "keep on", whatever that means. Is there an
     /// automatic standby mode?
     KeepOn,
+    /// Count the number of files on the given storage device (commands
+    /// 0x47, 0x48 and 0x49 for `Tf`, `UDisk` and `Flash`)
+    GetFileCount(Storage),
+    /// Query the currently selected track on the given storage device
+    /// (commands 0x4b, 0x4c and 0x4d for `Tf`, `UDisk` and `Flash`)
+    GetCurrentTrack(Storage),
+    /// Play the specified track from the given folder, for cards with
+    /// more than 255 files in a folder. The folder is packed into the
+    /// top 4 bits of the parameter and the track into the bottom 12
+    /// bits, as in the `playLargeFolder` function of the official
+    /// arduino library, so folder 3 and track 999 refers to the file
+    /// named '0999.mp3' in the folder '03'. Construct it with
+    /// `Command::try_set_folder_track` or `FolderTrack::new`, which
+    /// check the ranges.
+    ///
+    /// This shares command byte 0x14 with `AdvertiseFolder` and the two
+    /// parameter encodings can't be told apart, so `Command::parse`
+    /// deliberately never produces this variant: every 0x14 packet is
+    /// parsed as `AdvertiseFolder`.
+    SetFolderTrack(FolderTrack),
+
+    /// Send an arbitrary command byte and parameter, e.g. for
+    /// firmware-specific commands supported by clone modules which
+    /// aren't in the datasheet. This is never produced by
+    /// `Command::parse`, which will return the documented variant for
+    /// known command bytes and an `Error::InvalidCommand` otherwise.
+    #[allow(missing_docs)]
+    Custom { cmd: u8, param: u16 },
+}
+
+// the per-source queries used to be separate variants
+impl Command {
+    /// Count the number of files on the attached SD card
+    #[deprecated(
+        since = "0.1.2",
+        note = "Use Command::GetFileCount(Storage::Tf) instead"
+    )]
+    #[allow(non_upper_case_globals)]
+    pub const GetTfFileCount: Command = Command::GetFileCount(Storage::Tf);
+
+    /// Count the number of files on the attached UDisk
+    #[deprecated(
+        since = "0.1.2",
+        note = "Use Command::GetFileCount(Storage::UDisk) instead"
+    )]
+    #[allow(non_upper_case_globals)]
+    pub const GetUDiskFileCount: Command =
+        Command::GetFileCount(Storage::UDisk);
+
+    /// Count the number of files on the attached flash chip
+    #[deprecated(
+        since = "0.1.2",
+        note = "Use Command::GetFileCount(Storage::Flash) instead"
+    )]
+    #[allow(non_upper_case_globals)]
+    pub const GetFlashFileCount: Command =
+        Command::GetFileCount(Storage::Flash);
+
     /// Query the currently selected track on the SD card source
-    GetTfCurrentTrack,
+    #[deprecated(
+        since = "0.1.2",
+        note = "Use Command::GetCurrentTrack(Storage::Tf) instead"
+    )]
+    #[allow(non_upper_case_globals)]
+    pub const GetTfCurrentTrack: Command =
+        Command::GetCurrentTrack(Storage::Tf);
+
     /// Query the currently selected track on the UDisk source
-    GetUDiskCurrentTrack,
+    #[deprecated(
+        since = "0.1.2",
+        note = "Use Command::GetCurrentTrack(Storage::UDisk) instead"
+    )]
+    #[allow(non_upper_case_globals)]
+    pub const GetUDiskCurrentTrack: Command =
+        Command::GetCurrentTrack(Storage::UDisk);
+
     /// Query the currently selected track on the flash chip source
-    GetFlashCurrentTrack,
+    #[deprecated(
+        since = "0.1.2",
+        note = "Use Command::GetCurrentTrack(Storage::Flash) instead"
+    )]
+    #[allow(non_upper_case_globals)]
+    pub const GetFlashCurrentTrack: Command =
+        Command::GetCurrentTrack(Storage::Flash);
 }
 
-/// EQ presets supported by the device
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// EQ presets supported by the device. They are ordered by their
+/// parameter values.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(u16)]
 #[allow(missing_docs)]
 pub enum EqMode {
@@ -124,12 +263,132 @@ pub enum EqMode {
     Rock = 0x02,
     Jazz = 0x03,
     Classic = 0x04,
-    Base = 0x05,
+    /// Previously named `Base`, which is still accepted when
+    /// deserialising
+    #[cfg_attr(feature = "use_serde", serde(alias = "Base"))]
+    Bass = 0x05,
+}
+
+impl TryFrom<u16> for EqMode {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self> {
+        use EqMode::*;
+        Ok(match value {
+            0x00 => Normal,
+            0x01 => Pop,
+            0x02 => Rock,
+            0x03 => Jazz,
+            0x04 => Classic,
+            0x05 => Bass,
+            _ => return Err(Error::InvalidParameterValue(value)),
+        })
+    }
+}
+
+impl From<EqMode> for u16 {
+    fn from(eq: EqMode) -> Self {
+        eq as u16
+    }
+}
+
+impl EqMode {
+    const ALL: [Self; 6] = [
+        Self::Normal,
+        Self::Pop,
+        Self::Rock,
+        Self::Jazz,
+        Self::Classic,
+        Self::Bass,
+    ];
+
+    /// Number of EQ presets
+    pub const fn count() -> usize {
+        Self::ALL.len()
+    }
+
+    /// All of the EQ presets in order
+    pub fn all() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter()
+    }
+
+    /// The EQ preset at position `index` in `EqMode::all`
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// The following preset, wrapping around from `Bass` to `Normal`
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::count()]
+    }
+
+    /// The preceding preset, wrapping around from `Normal` to `Bass`
+    pub fn prev(self) -> Self {
+        Self::ALL[(self as usize + Self::count() - 1) % Self::count()]
+    }
+}
+
+impl EqMode {
+    const NAMES: &'static [(&'static str, Self)] = &[
+        ("Normal", Self::Normal),
+        ("Pop", Self::Pop),
+        ("Rock", Self::Rock),
+        ("Jazz", Self::Jazz),
+        ("Classic", Self::Classic),
+        ("Bass", Self::Bass),
+        ("Base", Self::Bass),
+    ];
+
+    /// Look up an EQ mode by name, ignoring case. The old variant name
+    /// "Base" is accepted as an alias for "Bass".
+    pub fn try_from_str(name: &str) -> Option<Self> {
+        lookup(Self::NAMES, name)
+    }
+
+    /// The old name of `EqMode::Bass`
+    #[deprecated(since = "0.1.2", note = "Use EqMode::Bass instead")]
+    #[allow(non_upper_case_globals)]
+    pub const Base: EqMode = EqMode::Bass;
+}
+
+impl FromStr for EqMode {
+    type Err = Error;
+
+    /// Parse an EQ mode as with `EqMode::try_from_str`, returning
+    /// `Error::UnknownName` if the name isn't recognised
+    fn from_str(name: &str) -> Result<Self> {
+        Self::try_from_str(name).ok_or(Error::UnknownName)
+    }
+}
+
+/// Find `name` in a table of names, ignoring case
+fn lookup<T: Copy>(names: &[(&str, T)], name: &str) -> Option<T> {
+    names
+        .iter()
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+        .map(|(_, value)| *value)
+}
+
+impl Display for EqMode {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        use EqMode::*;
+        fmt.write_str(match self {
+            Normal => "Normal",
+            Pop => "Pop",
+            Rock => "Rock",
+            Jazz => "Jazz",
+            Classic => "Classic",
+            Bass => "Bass",
+        })
+    }
 }
 
-/// Repeat modes supported by the device
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+/// Repeat modes supported by the device. They are ordered by their
+/// parameter values.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(u16)]
 #[allow(missing_docs)]
 pub enum PlaybackMode {
@@ -139,10 +398,107 @@ pub enum PlaybackMode {
     Random = 0x03,
 }
 
-/// Input data sources supported by the device. I don't know what
-/// `Sleep` means here.
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+impl TryFrom<u16> for PlaybackMode {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self> {
+        use PlaybackMode::*;
+        Ok(match value {
+            0x00 => Repeat,
+            0x01 => FolderRepeat,
+            0x02 => SingleRepeat,
+            0x03 => Random,
+            _ => return Err(Error::InvalidParameterValue(value)),
+        })
+    }
+}
+
+impl From<PlaybackMode> for u16 {
+    fn from(mode: PlaybackMode) -> Self {
+        mode as u16
+    }
+}
+
+impl PlaybackMode {
+    const ALL: [Self; 4] = [
+        Self::Repeat,
+        Self::FolderRepeat,
+        Self::SingleRepeat,
+        Self::Random,
+    ];
+
+    /// Number of playback modes
+    pub const fn count() -> usize {
+        Self::ALL.len()
+    }
+
+    /// All of the playback modes in order
+    pub fn all() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter()
+    }
+
+    /// The playback mode at position `index` in `PlaybackMode::all`
+    pub fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    /// The following mode, wrapping around from `Random` to `Repeat`
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::count()]
+    }
+
+    /// The preceding mode, wrapping around from `Repeat` to `Random`
+    pub fn prev(self) -> Self {
+        Self::ALL[(self as usize + Self::count() - 1) % Self::count()]
+    }
+}
+
+impl PlaybackMode {
+    const NAMES: &'static [(&'static str, Self)] = &[
+        ("Repeat All", Self::Repeat),
+        ("Repeat", Self::Repeat),
+        ("Folder Repeat", Self::FolderRepeat),
+        ("FolderRepeat", Self::FolderRepeat),
+        ("Single Repeat", Self::SingleRepeat),
+        ("SingleRepeat", Self::SingleRepeat),
+        ("Random", Self::Random),
+    ];
+
+    /// Look up a playback mode by either its display name (e.g.
+    /// "Repeat All") or its variant name (e.g. "Repeat"), ignoring case
+    pub fn try_from_str(name: &str) -> Option<Self> {
+        lookup(Self::NAMES, name)
+    }
+}
+
+impl FromStr for PlaybackMode {
+    type Err = Error;
+
+    /// Parse a playback mode as with `PlaybackMode::try_from_str`,
+    /// returning `Error::UnknownName` if the name isn't recognised
+    fn from_str(name: &str) -> Result<Self> {
+        Self::try_from_str(name).ok_or(Error::UnknownName)
+    }
+}
+
+impl Display for PlaybackMode {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        use PlaybackMode::*;
+        fmt.write_str(match self {
+            Repeat => "Repeat All",
+            FolderRepeat => "Folder Repeat",
+            SingleRepeat => "Single Repeat",
+            Random => "Random",
+        })
+    }
+}
+
+/// Input data sources supported by the device, ordered by their
+/// parameter values. I don't know what `Sleep` means here.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(u16)]
 #[allow(missing_docs)]
 pub enum PlaybackSource {
@@ -153,9 +509,159 @@ pub enum PlaybackSource {
     Flash = 0x04,
 }
 
+impl TryFrom<u16> for PlaybackSource {
+    type Error = Error;
+
+    fn try_from(value: u16) -> Result<Self> {
+        use PlaybackSource::*;
+        Ok(match value {
+            0x00 => UDisk,
+            0x01 => Tf,
+            0x02 => Aux,
+            0x03 => Sleep,
+            0x04 => Flash,
+            _ => return Err(Error::InvalidParameterValue(value)),
+        })
+    }
+}
+
+impl From<PlaybackSource> for u16 {
+    fn from(source: PlaybackSource) -> Self {
+        source as u16
+    }
+}
+
+impl PlaybackSource {
+    const ALL: [Self; 5] =
+        [Self::UDisk, Self::Tf, Self::Aux, Self::Sleep, Self::Flash];
+
+    /// All of the playback sources in order
+    pub fn all() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter()
+    }
+
+    /// The query for the number of files on this source. `Aux` and
+    /// `Sleep` have no files so there is no query for them.
+    pub fn file_count_command(self) -> Option<Command> {
+        self.to_storage().map(Command::GetFileCount)
+    }
+
+    /// The query for the current track on this source. `Aux` and
+    /// `Sleep` have no tracks so there is no query for them.
+    pub fn current_track_command(self) -> Option<Command> {
+        self.to_storage().map(Command::GetCurrentTrack)
+    }
+
+    /// The storage device corresponding to this source, if there is one
+    pub fn to_storage(self) -> Option<Storage> {
+        use PlaybackSource::*;
+        match self {
+            UDisk => Some(Storage::UDisk),
+            Tf => Some(Storage::Tf),
+            Flash => Some(Storage::Flash),
+            Aux | Sleep => None,
+        }
+    }
+
+    /// The command which selects this source for playback
+    pub fn set_source_command(self) -> Command {
+        Command::SetPlaybackSource(self)
+    }
+
+    /// The disk corresponding to this source, if there is one
+    pub fn to_disk(self) -> Option<Disk> {
+        use PlaybackSource::*;
+        match self {
+            UDisk => Some(Disk::UDisk),
+            Tf => Some(Disk::Tf),
+            Flash => Some(Disk::Flash),
+            Aux | Sleep => None,
+        }
+    }
+
+    const NAMES: &'static [(&'static str, Self)] = &[
+        ("UDisk", Self::UDisk),
+        ("Tf", Self::Tf),
+        ("SD", Self::Tf),
+        ("SDCard", Self::Tf),
+        ("Aux", Self::Aux),
+        ("Sleep", Self::Sleep),
+        ("Flash", Self::Flash),
+    ];
+
+    /// Look up a playback source by name, ignoring case. "SD" and
+    /// "SDCard" are accepted as aliases for `Tf`.
+    pub fn try_from_str(name: &str) -> Option<Self> {
+        lookup(Self::NAMES, name)
+    }
+}
+
+impl Display for PlaybackSource {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        use PlaybackSource::*;
+        fmt.write_str(match self {
+            UDisk => "USB disk",
+            Tf => "SD card",
+            Aux => "Aux",
+            Sleep => "Sleep",
+            Flash => "Flash",
+        })
+    }
+}
+
+impl FromStr for PlaybackSource {
+    type Err = Error;
+
+    /// Parse a playback source as with `PlaybackSource::try_from_str`,
+    /// returning `Error::UnknownName` if the name isn't recognised
+    fn from_str(name: &str) -> Result<Self> {
+        Self::try_from_str(name).ok_or(Error::UnknownName)
+    }
+}
+
+/// Storage devices which hold files, i.e. the playback sources which
+/// can be queried for their file count and current track. They are
+/// ordered by their query command bytes.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
+#[repr(u8)]
+#[allow(missing_docs)]
+pub enum Storage {
+    Tf = 0x00,
+    UDisk = 0x01,
+    Flash = 0x02,
+}
+
+impl Storage {
+    /// The playback source which plays from this storage device
+    pub fn to_playback_source(self) -> PlaybackSource {
+        match self {
+            Self::Tf => PlaybackSource::Tf,
+            Self::UDisk => PlaybackSource::UDisk,
+            Self::Flash => PlaybackSource::Flash,
+        }
+    }
+}
+
+impl From<Storage> for PlaybackSource {
+    fn from(storage: Storage) -> Self {
+        storage.to_playback_source()
+    }
+}
+
+impl Display for Storage {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        self.to_playback_source().fmt(fmt)
+    }
+}
+
 /// Whether to request an ACK from the device
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, TryFromPrimitive)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(u8)]
 #[allow(missing_docs)]
 pub enum RequestAck {
@@ -163,6 +669,31 @@ pub enum RequestAck {
     Yes = 0x01,
 }
 
+impl From<bool> for RequestAck {
+    fn from(request_ack: bool) -> Self {
+        if request_ack {
+            Self::Yes
+        } else {
+            Self::No
+        }
+    }
+}
+
+impl From<RequestAck> for bool {
+    fn from(request_ack: RequestAck) -> Self {
+        request_ack == RequestAck::Yes
+    }
+}
+
+impl Display for RequestAck {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Self::No => "No ACK",
+            Self::Yes => "ACK requested",
+        })
+    }
+}
+
 impl Command {
     /// Serialise the command into the provided buffer. The buffer size
     /// should be at least 10 bytes, otherwise an `Error::BufferTooShort`
@@ -172,6 +703,48 @@ impl Command {
         self.serialise_with_ack(buf, RequestAck::No)
     }
 
+    /// Serialise the command into a new packet without requesting an
+    /// ACK
+    pub fn to_bytes(&self) -> [u8; PACKET_LEN] {
+        self.to_bytes_with_ack(RequestAck::No)
+    }
+
+    /// Serialise the command into a new packet, optionally requesting
+    /// an ACK from the device
+    pub fn to_bytes_with_ack(
+        &self,
+        request_ack: impl Into<RequestAck>,
+    ) -> [u8; PACKET_LEN] {
+        let mut buf = [0u8; PACKET_LEN];
+        // the buffer is always large enough
+        let _ = self.serialise_with_ack(&mut buf, request_ack);
+        buf
+    }
+
+    /// Serialise the command into a new `Vec` of 10 bytes without
+    /// requesting an ACK
+    #[cfg(feature = "std")]
+    pub fn serialise_into_vec(&self) -> std::vec::Vec<u8> {
+        self.to_bytes().to_vec()
+    }
+
+    /// Serialise the command into a new `Vec` of 10 bytes, optionally
+    /// requesting an ACK from the device
+    #[cfg(feature = "std")]
+    pub fn serialise_into_vec_with_ack(
+        &self,
+        request_ack: impl Into<RequestAck>,
+    ) -> std::vec::Vec<u8> {
+        self.to_bytes_with_ack(request_ack).to_vec()
+    }
+
+    /// Serialise each command without requesting an ACK and
+    /// concatenate the packets
+    #[cfg(feature = "std")]
+    pub fn serialise_all_into_vec(cmds: &[Command]) -> std::vec::Vec<u8> {
+        cmds.iter().flat_map(Command::to_bytes).collect()
+    }
+
     /// Serialise the command into the provided buffer, optionally
     /// requesting an ACK from the device. The buffer size should be at
     /// least 10 bytes, otherwise an `Error::BufferTooShort` will be
@@ -180,32 +753,188 @@ impl Command {
     pub fn serialise_with_ack(
         &self,
         buf: &mut [u8],
-        request_ack: RequestAck,
+        request_ack: impl Into<RequestAck>,
     ) -> Result<usize> {
-        const LEN: usize = 10;
+        self.serialise_packet(buf, request_ack.into())
+    }
 
-        if buf.len() < LEN {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "serialise_with_ack",
+            level = "debug",
+            target = "dfr0299::serialise",
+            skip(self, buf),
+            fields(cmd = ?self)
+        )
+    )]
+    fn serialise_packet(
+        &self,
+        buf: &mut [u8],
+        request_ack: RequestAck,
+    ) -> Result<usize> {
+        if buf.len() < PACKET_LEN {
             return Err(Error::BufferTooShort);
         }
 
         let param = self.param();
 
-        buf[0] = START;
-        buf[1] = VERSION;
-        buf[2] = 0x06; // LEN
-        buf[3] = self.command_byte();
-        buf[4] = request_ack as u8;
-        buf[5] = (param >> 8) as u8;
-        buf[6] = param as u8;
+        buf[IDX_START] = START;
+        buf[IDX_VERSION] = VERSION;
+        buf[IDX_LEN] = LEN;
+        buf[IDX_CMD] = self.command_byte();
+        buf[IDX_FEEDBACK] = request_ack as u8;
+        buf[IDX_PARAM_H] = (param >> 8) as u8;
+        buf[IDX_PARAM_L] = param as u8;
+
+        let checksum = calculate_checksum(
+            buf[IDX_VERSION],
+            buf[IDX_LEN],
+            buf[IDX_CMD],
+            buf[IDX_FEEDBACK],
+            buf[IDX_PARAM_H],
+            buf[IDX_PARAM_L],
+        );
+
+        buf[IDX_CHECKSUM_H] = (checksum >> 8) as u8;
+        buf[IDX_CHECKSUM_L] = checksum as u8;
+        buf[IDX_STOP] = STOP;
+
+        #[cfg(feature = "log")]
+        log::debug!(
+            target: "dfr0299::serialise",
+            "{self:?}: {:02x?}",
+            &buf[..PACKET_LEN]
+        );
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            target: "dfr0299::serialise",
+            bytes = ?&buf[..PACKET_LEN],
+            "serialised"
+        );
+        Ok(PACKET_LEN)
+    }
+
+    /// Serialise each command end-to-end into the buffer without
+    /// requesting ACKs, e.g. for writing a startup sequence in one
+    /// go. Returns the total number of bytes written, or
+    /// `Error::BufferTooShort` without writing anything if the buffer
+    /// can't hold all of the packets.
+    pub fn serialise_all(cmds: &[Command], buf: &mut [u8]) -> Result<usize> {
+        if buf.len() < cmds.len() * PACKET_LEN {
+            return Err(Error::BufferTooShort);
+        }
+        for (cmd, chunk) in cmds.iter().zip(buf.chunks_exact_mut(PACKET_LEN)) {
+            cmd.serialise_with_ack(chunk, RequestAck::No)?;
+        }
+        Ok(cmds.len() * PACKET_LEN)
+    }
 
-        // checksum is twos complement of the sum of the data bytes
-        let checksum: i16 = buf[1..7].iter().cloned().map(i16::from).sum();
-        let checksum = -checksum;
+    /// Serialise each command end-to-end into the buffer as with
+    /// `Command::serialise_all`, with a separate ACK flag per command
+    pub fn serialise_all_with_ack(
+        cmds: &[(Command, RequestAck)],
+        buf: &mut [u8],
+    ) -> Result<usize> {
+        if buf.len() < cmds.len() * PACKET_LEN {
+            return Err(Error::BufferTooShort);
+        }
+        for ((cmd, ack), chunk) in
+            cmds.iter().zip(buf.chunks_exact_mut(PACKET_LEN))
+        {
+            cmd.serialise_with_ack(chunk, *ack)?;
+        }
+        Ok(cmds.len() * PACKET_LEN)
+    }
 
-        buf[7] = (checksum >> 8) as u8;
-        buf[8] = checksum as u8;
-        buf[9] = STOP;
-        Ok(LEN)
+    /// Parse a serialised command packet, as produced by
+    /// `Command::serialise_with_ack`, returning the command and whether
+    /// it requested an ACK. This is the inverse of serialisation and is
+    /// intended for sniffing traffic from a host to the device.
+    ///
+    /// The START, VERSION, LEN and STOP bytes and the checksum are all
+    /// validated before the command itself is parsed.
+    pub fn try_from_bytes(buf: &[u8; 10]) -> Result<(Self, RequestAck)> {
+        Packet::from_bytes(buf)?.as_command()
+    }
+
+    /// Parse a command byte and its two parameter bytes into a
+    /// `Command`. This reverses `Command::command_byte` and
+    /// `Command::param`. If the command byte does not match any known
+    /// command then the byte is returned with an
+    /// `Error::InvalidCommand`, and if the parameter is not valid for
+    /// the command an `Error::InvalidParameterValue` is returned.
+    pub fn parse(cmd: u8, param_h: u8, param_l: u8) -> Result<Self> {
+        use Command::*;
+
+        let param = u16::from_be_bytes([param_h, param_l]);
+        let flag = |value: u16| match value {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::InvalidParameterValue(value)),
+        };
+        let byte = |value: u16| {
+            u8::try_from(value).map_err(|_| Error::InvalidParameterValue(value))
+        };
+
+        Ok(match cmd {
+            // "Control" messages
+            0x01 => Next,
+            0x02 => Previous,
+            0x03 => Track(param),
+            0x04 => IncreaseVolume,
+            0x05 => DecreaseVolume,
+            0x06 => SetVolume(param),
+            0x07 => SetEq(EqMode::try_from(param)?),
+            0x08 => SetPlaybackMode(PlaybackMode::try_from(param)?),
+            0x09 => SetPlaybackSource(PlaybackSource::try_from(param)?),
+            0x0a => Standby,
+            0x0b => Wake,
+            0x0c => Reset,
+            0x0d => Playback,
+            0x0e => Pause,
+            0x0f => SetFolder {
+                folder: param_h,
+                file: param_l,
+            },
+            0x10 => SetVolumeAdjust {
+                enable: flag(param_h.into())?,
+                gain: param_l,
+            },
+            0x11 => RepeatPlay(flag(param)?),
+            0x13 => AdvertiseTrack(param),
+            0x14 => AdvertiseFolder {
+                folder: param_h,
+                track: param_l,
+            },
+            0x15 => StopAdvertise,
+            0x16 => Stop,
+            0x17 => LoopFolder(byte(param)?),
+            0x18 => RandomPlay,
+            0x19 => LoopCurrentTrack(!flag(param)?),
+            0x1a => EnableDac(!flag(param)?),
+
+            // "Command" messages
+            0x3c => Stay1,
+            0x3d => Stay2,
+            0x3e => Stay3,
+            0x3f => InitialisationParameters(param),
+            0x40 => RequestRetransmission,
+            0x41 => Reply,
+            0x42 => GetStatus,
+            0x43 => GetVolume,
+            0x44 => GetEq,
+            0x45 => GetPlaybackMode,
+            0x46 => GetSoftwareVersion,
+            0x47 => GetFileCount(Storage::Tf),
+            0x48 => GetFileCount(Storage::UDisk),
+            0x49 => GetFileCount(Storage::Flash),
+            0x4a => KeepOn,
+            0x4b => GetCurrentTrack(Storage::Tf),
+            0x4c => GetCurrentTrack(Storage::UDisk),
+            0x4d => GetCurrentTrack(Storage::Flash),
+            cmd => return Err(Error::InvalidCommand(cmd)),
+        })
     }
 
     /// Get the u8 value associated with the current Command.
@@ -234,6 +963,14 @@ impl Command {
             SetFolder { .. } => 0x0f,
             SetVolumeAdjust { .. } => 0x10,
             RepeatPlay(_) => 0x11,
+            AdvertiseTrack(_) => 0x13,
+            AdvertiseFolder { .. } | SetFolderTrack(_) => 0x14,
+            StopAdvertise => 0x15,
+            Stop => 0x16,
+            LoopFolder(_) => 0x17,
+            RandomPlay => 0x18,
+            LoopCurrentTrack(_) => 0x19,
+            EnableDac(_) => 0x1a,
 
             // "Command messages"
             Stay1 => 0x3c,
@@ -242,18 +979,16 @@ impl Command {
             InitialisationParameters(_) => 0x3f,
             RequestRetransmission => 0x40,
             Reply => 0x41,
-            GetStatus => 0x41,
+            GetStatus => 0x42,
             GetVolume => 0x43,
             GetEq => 0x44,
             GetPlaybackMode => 0x45,
             GetSoftwareVersion => 0x46,
-            GetTfFileCount => 0x47,
-            GetUDiskFileCount => 0x48,
-            GetFlashFileCount => 0x49,
             KeepOn => 0x4a,
-            GetTfCurrentTrack => 0x4b,
-            GetUDiskCurrentTrack => 0x4c,
-            GetFlashCurrentTrack => 0x4d,
+            GetFileCount(storage) => 0x47 + *storage as u8,
+            GetCurrentTrack(storage) => 0x4b + *storage as u8,
+
+            Custom { cmd, .. } => *cmd,
         }
     }
 
@@ -265,17 +1000,201 @@ impl Command {
         match self {
             Track(t) => *t,
             SetVolume(v) => *v,
-            SetEq(e) => *e as u16,
-            SetPlaybackMode(m) => *m as u16,
-            SetPlaybackSource(s) => *s as u16,
-            SetFolder { folder, file } => u16::from_be_bytes([*folder, *file]),
+            SetEq(e) => u16::from(*e),
+            SetPlaybackMode(m) => u16::from(*m),
+            SetPlaybackSource(s) => u16::from(*s),
+            SetFolder { folder, file } => folder_param(*folder, *file),
             SetVolumeAdjust { enable, gain } => {
                 u16::from_be_bytes([*enable as u8, *gain])
             }
             RepeatPlay(r) => *r as u16,
+            AdvertiseTrack(t) => *t,
+            AdvertiseFolder { folder, track } => folder_param(*folder, *track),
+            SetFolderTrack(folder_track) => {
+                folder_track.folder() << 12 | folder_track.track()
+            }
+            LoopFolder(f) => u16::from(*f),
+            LoopCurrentTrack(l) => !*l as u16,
+            EnableDac(e) => !*e as u16,
+            InitialisationParameters(p) => *p,
+            Custom { param, .. } => *param,
             _ => 0,
         }
     }
+
+    /// Whether this command queries the device for some information,
+    /// as opposed to controlling it
+    pub fn is_query(&self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            GetStatus
+                | GetVolume
+                | GetEq
+                | GetPlaybackMode
+                | GetSoftwareVersion
+                | GetFileCount(_)
+                | GetCurrentTrack(_)
+        )
+    }
+
+    /// The command byte of the response which the device sends back
+    /// for a query command, or `None` if this isn't a query. The device
+    /// answers queries using the same command byte as the query.
+    pub fn expected_response_cmd(&self) -> Option<u8> {
+        self.is_query().then(|| self.command_byte())
+    }
+
+    /// Whether this command carries a parameter value
+    pub fn has_parameter(&self) -> bool {
+        use Command::*;
+        matches!(
+            self,
+            Track(_)
+                | SetVolume(_)
+                | SetEq(_)
+                | SetPlaybackMode(_)
+                | SetPlaybackSource(_)
+                | SetFolder { .. }
+                | SetVolumeAdjust { .. }
+                | RepeatPlay(_)
+                | AdvertiseTrack(_)
+                | AdvertiseFolder { .. }
+                | SetFolderTrack(_)
+                | LoopFolder(_)
+                | LoopCurrentTrack(_)
+                | EnableDac(_)
+                | InitialisationParameters(_)
+                | Custom { .. }
+        )
+    }
+}
+
+impl TryFrom<u8> for Command {
+    type Error = Error;
+
+    /// Look up a command which has no parameter from its command byte,
+    /// e.g. when only the command byte has been logged. Returns
+    /// `Error::CommandRequiresParameter` for commands which carry a
+    /// parameter, since the parameter can't be recovered, and
+    /// `Error::InvalidCommand` for unknown command bytes.
+    fn try_from(byte: u8) -> Result<Self> {
+        let cmd = Self::parse(byte, 0x00, 0x00)?;
+        if cmd.has_parameter() {
+            return Err(Error::CommandRequiresParameter(byte));
+        }
+        Ok(cmd)
+    }
+}
+
+impl TryFrom<[u8; 10]> for Command {
+    type Error = Error;
+
+    /// Parse a serialised command packet, discarding the ACK flag. See
+    /// `Command::try_from_bytes`.
+    fn try_from(buf: [u8; 10]) -> Result<Self> {
+        Self::try_from_bytes(&buf).map(|(cmd, _)| cmd)
+    }
+}
+
+/// Folder and file numbers are packed into the high and low bytes of
+/// the parameter respectively
+fn folder_param(folder: u8, file: u8) -> u16 {
+    u16::from_be_bytes([folder, file])
+}
+
+#[cfg(feature = "heapless")]
+impl Command {
+    /// The `Display` description of the command in a fixed-capacity
+    /// string, e.g. for showing on a small screen. Descriptions longer
+    /// than 64 bytes are truncated.
+    pub fn to_heapless_string(&self) -> heapless::String<64> {
+        crate::response::display_truncated(self)
+    }
+}
+
+impl Display for Command {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        use Command::*;
+        let on_off = |enable: bool| if enable { "on" } else { "off" };
+        match self {
+            Next => write!(fmt, "Next track"),
+            Previous => write!(fmt, "Previous track"),
+            Track(track) => write!(fmt, "Play track {track}"),
+            IncreaseVolume => write!(fmt, "Increase volume"),
+            DecreaseVolume => write!(fmt, "Decrease volume"),
+            SetVolume(volume) => {
+                write!(fmt, "Set volume to {volume} (of 30)")
+            }
+            SetEq(eq) => write!(fmt, "Set EQ to {eq}"),
+            SetPlaybackMode(mode) => {
+                write!(fmt, "Set playback mode to {mode}")
+            }
+            SetPlaybackSource(source) => {
+                write!(fmt, "Set playback source to {source}")
+            }
+            Standby => write!(fmt, "Enter standby"),
+            Wake => write!(fmt, "Wake from standby"),
+            Reset => write!(fmt, "Reset module"),
+            Playback => write!(fmt, "Start playback"),
+            Pause => write!(fmt, "Pause playback"),
+            SetFolder { folder, file } => {
+                write!(fmt, "Play folder {folder:02}, track {file:04}")
+            }
+            SetVolumeAdjust { enable, gain } => write!(
+                fmt,
+                "Set volume adjust {} with gain {gain}",
+                on_off(*enable)
+            ),
+            RepeatPlay(repeat) => {
+                write!(fmt, "Turn repeat play {}", on_off(*repeat))
+            }
+            AdvertiseTrack(track) => {
+                write!(fmt, "Play advert {track:04}")
+            }
+            AdvertiseFolder { folder, track } => {
+                write!(
+                    fmt,
+                    "Play advert from folder {folder:02}, track {track:04}"
+                )
+            }
+            StopAdvertise => write!(fmt, "Stop advert"),
+            Stop => write!(fmt, "Stop playback"),
+            LoopFolder(folder) => write!(fmt, "Loop folder {folder:02}"),
+            RandomPlay => write!(fmt, "Play all tracks randomly"),
+            LoopCurrentTrack(enable) => {
+                write!(fmt, "Turn looping of current track {}", on_off(*enable))
+            }
+            EnableDac(enable) => write!(fmt, "Turn DAC {}", on_off(*enable)),
+            Stay1 => write!(fmt, "Stay (1)"),
+            Stay2 => write!(fmt, "Stay (2)"),
+            Stay3 => write!(fmt, "Stay (3)"),
+            InitialisationParameters(param) => {
+                write!(fmt, "Send initialisation parameters {param:#06x}")
+            }
+            RequestRetransmission => write!(fmt, "Request retransmission"),
+            Reply => write!(fmt, "Reply"),
+            GetStatus => write!(fmt, "Query status"),
+            GetVolume => write!(fmt, "Query volume"),
+            GetEq => write!(fmt, "Query EQ"),
+            GetPlaybackMode => write!(fmt, "Query playback mode"),
+            GetSoftwareVersion => write!(fmt, "Query software version"),
+            KeepOn => write!(fmt, "Keep on"),
+            GetFileCount(storage) => write!(fmt, "Query {storage} file count"),
+            GetCurrentTrack(storage) => {
+                write!(fmt, "Query {storage} current track")
+            }
+            SetFolderTrack(folder_track) => write!(
+                fmt,
+                "Play folder {:02}, track {:04}",
+                folder_track.folder(),
+                folder_track.track()
+            ),
+            Custom { cmd, param } => {
+                write!(fmt, "Custom command {cmd:#04x} with {param:#06x}")
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -283,6 +1202,603 @@ mod test {
     use super::*;
     use pretty_assertions::assert_eq;
 
+    /// One instance of every `Command` variant, except for
+    /// `Command::Custom` which can duplicate any of the others and
+    /// `Command::SetFolderTrack` which duplicates `AdvertiseFolder`
+    const ONE_OF_EACH: &[Command] = {
+        use Command::*;
+        &[
+            Next,
+            Previous,
+            Track(1),
+            IncreaseVolume,
+            DecreaseVolume,
+            SetVolume(30),
+            SetEq(EqMode::Rock),
+            SetPlaybackMode(PlaybackMode::Random),
+            SetPlaybackSource(PlaybackSource::Tf),
+            Standby,
+            Wake,
+            Reset,
+            Playback,
+            Pause,
+            SetFolder {
+                folder: 4,
+                file: 123,
+            },
+            SetVolumeAdjust {
+                enable: true,
+                gain: 31,
+            },
+            RepeatPlay(true),
+            AdvertiseTrack(1),
+            AdvertiseFolder {
+                folder: 2,
+                track: 5,
+            },
+            StopAdvertise,
+            Stop,
+            LoopFolder(2),
+            RandomPlay,
+            LoopCurrentTrack(true),
+            EnableDac(true),
+            Stay1,
+            Stay2,
+            Stay3,
+            InitialisationParameters(0x0f),
+            RequestRetransmission,
+            Reply,
+            GetStatus,
+            GetVolume,
+            GetEq,
+            GetPlaybackMode,
+            GetSoftwareVersion,
+            GetFileCount(Storage::Tf),
+            GetFileCount(Storage::UDisk),
+            GetFileCount(Storage::Flash),
+            KeepOn,
+            GetCurrentTrack(Storage::Tf),
+            GetCurrentTrack(Storage::UDisk),
+            GetCurrentTrack(Storage::Flash),
+        ]
+    };
+
+    #[test]
+    fn command_bytes_are_unique() {
+        for (i, a) in ONE_OF_EACH.iter().enumerate() {
+            for b in &ONE_OF_EACH[i + 1..] {
+                assert_ne!(
+                    a.command_byte(),
+                    b.command_byte(),
+                    "{a:?} and {b:?} have the same command byte"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_bytes() {
+        let extras = [
+            Command::Track(0xffff),
+            Command::SetVolumeAdjust {
+                enable: false,
+                gain: 0,
+            },
+            Command::RepeatPlay(false),
+            Command::LoopCurrentTrack(false),
+            Command::EnableDac(false),
+        ];
+        for &cmd in ONE_OF_EACH.iter().chain(&extras) {
+            for ack in [RequestAck::No, RequestAck::Yes] {
+                let mut buf = [0; 10];
+                cmd.serialise_with_ack(&mut buf, ack).unwrap();
+                assert_eq!(Command::try_from_bytes(&buf).unwrap(), (cmd, ack));
+                assert_eq!(Command::try_from(buf).unwrap(), cmd);
+            }
+        }
+    }
+
+    #[test]
+    fn try_from_bytes_errors() {
+        let mut good = [0; 10];
+        Command::Track(1).serialise(&mut good).unwrap();
+
+        let corrupt = |idx: usize, value: u8| {
+            let mut buf = good;
+            buf[idx] = value;
+            Command::try_from_bytes(&buf).unwrap_err()
+        };
+        assert_eq!(corrupt(0, 0x00), Error::BadStart);
+        assert_eq!(corrupt(1, 0x00), Error::BadVersion);
+        assert_eq!(corrupt(2, 0x07), Error::BadLength);
+        assert_eq!(corrupt(9, 0x00), Error::BadStop);
+        assert_eq!(
+            corrupt(6, 0x02),
+            Error::BadChecksum {
+                expected: [0xfe, 0xf6],
+                received: [0xfe, 0xf7],
+            }
+        );
+
+        // valid packet framing around an unknown command byte
+        let mut buf = [0x7e, 0xff, 0x06, 0x30, 0x00, 0x00, 0x00, 0, 0, 0xef];
+        let checksum = -(0xff + 0x06 + 0x30_i16);
+        buf[7..9].copy_from_slice(&checksum.to_be_bytes());
+        assert_eq!(
+            Command::try_from_bytes(&buf).unwrap_err(),
+            Error::InvalidCommand(0x30)
+        );
+    }
+
+    #[test]
+    fn hash_map_keys() {
+        use std::collections::{HashMap, HashSet};
+
+        let map: HashMap<Command, usize> = ONE_OF_EACH
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(i, cmd)| (cmd, i))
+            .collect();
+        assert_eq!(map.len(), ONE_OF_EACH.len());
+        for (i, cmd) in ONE_OF_EACH.iter().enumerate() {
+            assert_eq!(map[cmd], i);
+        }
+
+        // struct variants hash all of their fields
+        let folders: HashSet<_> = [
+            Command::SetFolder { folder: 1, file: 2 },
+            Command::SetFolder { folder: 2, file: 1 },
+            Command::SetFolder { folder: 1, file: 2 },
+            Command::SetVolumeAdjust {
+                enable: true,
+                gain: 3,
+            },
+            Command::SetVolumeAdjust {
+                enable: false,
+                gain: 3,
+            },
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(folders.len(), 4);
+        assert!(folders.contains(&Command::SetFolder { folder: 2, file: 1 }));
+        assert!(!folders.contains(&Command::SetFolder { folder: 2, file: 2 }));
+
+        let eq: HashSet<_> = [EqMode::Pop, EqMode::Rock, EqMode::Pop].into();
+        assert_eq!(eq.len(), 2);
+        let modes: HashSet<_> =
+            [PlaybackMode::Random, PlaybackMode::Repeat].into();
+        assert!(modes.contains(&PlaybackMode::Random));
+        let sources: HashMap<_, _> =
+            [(PlaybackSource::Tf, "SD"), (PlaybackSource::UDisk, "USB")].into();
+        assert_eq!(sources[&PlaybackSource::Tf], "SD");
+        let acks: HashSet<_> = [RequestAck::No, RequestAck::Yes].into();
+        assert_eq!(acks.len(), 2);
+    }
+
+    #[test]
+    fn eq_mode_cycle() {
+        use EqMode::*;
+        assert_eq!(EqMode::count(), 6);
+        assert_eq!(
+            EqMode::all().collect::<Vec<_>>(),
+            [Normal, Pop, Rock, Jazz, Classic, Bass]
+        );
+        for (i, eq) in EqMode::all().enumerate() {
+            assert_eq!(EqMode::from_index(i), Some(eq));
+            assert_eq!(eq.next().prev(), eq);
+        }
+        assert_eq!(EqMode::from_index(6), None);
+
+        assert_eq!(Normal.next(), Pop);
+        assert_eq!(Bass.next(), Normal);
+        assert_eq!(Normal.prev(), Bass);
+        assert_eq!(Pop.prev(), Normal);
+
+        // cycling all the way round visits every preset once
+        let mut eq = Rock;
+        let mut seen = Vec::new();
+        for _ in 0..EqMode::count() {
+            seen.push(eq);
+            eq = eq.next();
+        }
+        assert_eq!(eq, Rock);
+        seen.sort();
+        assert_eq!(seen, EqMode::ALL);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn eq_mode_deprecated_base() {
+        assert_eq!(EqMode::Base, EqMode::Bass);
+        assert_eq!(u16::from(EqMode::Base), 0x05);
+        assert!(matches!(EqMode::Bass, EqMode::Base));
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn eq_mode_deserialise_old_name() {
+        assert_eq!(
+            serde_json::from_str::<EqMode>(r#""Base""#).unwrap(),
+            EqMode::Bass
+        );
+        assert_eq!(serde_json::to_string(&EqMode::Bass).unwrap(), r#""Bass""#);
+    }
+
+    #[test]
+    fn eq_mode_display() {
+        let names: Vec<_> = EqMode::all().map(|eq| eq.to_string()).collect();
+        assert_eq!(names, ["Normal", "Pop", "Rock", "Jazz", "Classic", "Bass"]);
+    }
+
+    #[test]
+    fn playback_mode_cycle() {
+        use PlaybackMode::*;
+        assert_eq!(PlaybackMode::count(), 4);
+        assert_eq!(
+            PlaybackMode::all().collect::<Vec<_>>(),
+            [Repeat, FolderRepeat, SingleRepeat, Random]
+        );
+        for (i, mode) in PlaybackMode::all().enumerate() {
+            assert_eq!(PlaybackMode::from_index(i), Some(mode));
+            assert_eq!(mode.next().prev(), mode);
+        }
+        assert_eq!(PlaybackMode::from_index(4), None);
+
+        assert_eq!(Repeat.next(), FolderRepeat);
+        assert_eq!(Random.next(), Repeat);
+        assert_eq!(Repeat.prev(), Random);
+        assert_eq!(SingleRepeat.prev(), FolderRepeat);
+    }
+
+    #[test]
+    fn playback_mode_display() {
+        let names: Vec<_> =
+            PlaybackMode::all().map(|mode| mode.to_string()).collect();
+        assert_eq!(
+            names,
+            ["Repeat All", "Folder Repeat", "Single Repeat", "Random"]
+        );
+    }
+
+    #[test]
+    fn playback_source_commands() {
+        use PlaybackSource::*;
+        for (source, storage) in [
+            (UDisk, Some(Storage::UDisk)),
+            (Tf, Some(Storage::Tf)),
+            (Aux, None),
+            (Sleep, None),
+            (Flash, Some(Storage::Flash)),
+        ] {
+            assert_eq!(source.to_storage(), storage);
+            assert_eq!(
+                source.file_count_command(),
+                storage.map(Command::GetFileCount)
+            );
+            assert_eq!(
+                source.current_track_command(),
+                storage.map(Command::GetCurrentTrack)
+            );
+            assert_eq!(
+                source.set_source_command(),
+                Command::SetPlaybackSource(source)
+            );
+        }
+    }
+
+    #[test]
+    fn param_enums_from_str() {
+        use EqMode::*;
+        for (name, mode) in [
+            ("Normal", Normal),
+            ("pop", Pop),
+            ("ROCK", Rock),
+            ("jAzZ", Jazz),
+            ("classic", Classic),
+            ("Bass", Bass),
+            ("base", Bass),
+        ] {
+            assert_eq!(EqMode::try_from_str(name), Some(mode), "{name}");
+            assert_eq!(name.parse(), Ok(mode));
+        }
+        for mode in EqMode::all() {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+
+        for (name, mode) in [
+            ("repeat all", PlaybackMode::Repeat),
+            ("Repeat", PlaybackMode::Repeat),
+            ("FOLDER REPEAT", PlaybackMode::FolderRepeat),
+            ("folderrepeat", PlaybackMode::FolderRepeat),
+            ("Single Repeat", PlaybackMode::SingleRepeat),
+            ("SingleRepeat", PlaybackMode::SingleRepeat),
+            ("random", PlaybackMode::Random),
+        ] {
+            assert_eq!(PlaybackMode::try_from_str(name), Some(mode), "{name}");
+            assert_eq!(name.parse(), Ok(mode));
+        }
+        for mode in PlaybackMode::all() {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+
+        for (name, source) in [
+            ("udisk", PlaybackSource::UDisk),
+            ("TF", PlaybackSource::Tf),
+            ("sd", PlaybackSource::Tf),
+            ("SDCard", PlaybackSource::Tf),
+            ("sdcard", PlaybackSource::Tf),
+            ("Aux", PlaybackSource::Aux),
+            ("SLEEP", PlaybackSource::Sleep),
+            ("flash", PlaybackSource::Flash),
+        ] {
+            assert_eq!(
+                PlaybackSource::try_from_str(name),
+                Some(source),
+                "{name}"
+            );
+            assert_eq!(name.parse(), Ok(source));
+        }
+
+        for name in ["", "Metal", "Rock ", "repeat-all", "SD Card", "usb"] {
+            assert_eq!(EqMode::try_from_str(name), None);
+            assert_eq!(PlaybackMode::try_from_str(name), None);
+            assert_eq!(PlaybackSource::try_from_str(name), None);
+            assert_eq!(name.parse::<EqMode>(), Err(Error::UnknownName));
+            assert_eq!(name.parse::<PlaybackMode>(), Err(Error::UnknownName));
+            assert_eq!(name.parse::<PlaybackSource>(), Err(Error::UnknownName));
+        }
+    }
+
+    #[test]
+    fn command_display() {
+        use Command::*;
+        for (cmd, expected) in [
+            (SetVolume(25), "Set volume to 25 (of 30)"),
+            (Track(42), "Play track 42"),
+            (SetEq(EqMode::Rock), "Set EQ to Rock"),
+            (SetEq(EqMode::Bass), "Set EQ to Bass"),
+            (
+                SetPlaybackMode(PlaybackMode::SingleRepeat),
+                "Set playback mode to Single Repeat",
+            ),
+            (
+                SetPlaybackSource(PlaybackSource::Tf),
+                "Set playback source to SD card",
+            ),
+            (Reset, "Reset module"),
+            (Pause, "Pause playback"),
+            (
+                SetFolder { folder: 4, file: 1 },
+                "Play folder 04, track 0001",
+            ),
+            (
+                AdvertiseFolder {
+                    folder: 2,
+                    track: 5,
+                },
+                "Play advert from folder 02, track 0005",
+            ),
+            (EnableDac(false), "Turn DAC off"),
+            (
+                Custom {
+                    cmd: 0x5a,
+                    param: 0x1234,
+                },
+                "Custom command 0x5a with 0x1234",
+            ),
+        ] {
+            assert_eq!(cmd.to_string(), expected);
+        }
+
+        // every variant has a description distinct from the others
+        let descriptions: std::collections::HashSet<_> =
+            ONE_OF_EACH.iter().map(|cmd| cmd.to_string()).collect();
+        assert_eq!(descriptions.len(), ONE_OF_EACH.len());
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn heapless_strings() {
+        for cmd in ONE_OF_EACH {
+            assert_eq!(cmd.to_heapless_string(), cmd.to_string().as_str());
+        }
+        assert_eq!(
+            Command::SetFolder { folder: 4, file: 1 }.to_heapless_string(),
+            "Play folder 04, track 0001"
+        );
+    }
+
+    #[test]
+    fn playback_source_all() {
+        use PlaybackSource::*;
+        assert_eq!(
+            PlaybackSource::all().collect::<Vec<_>>(),
+            [UDisk, Tf, Aux, Sleep, Flash]
+        );
+        for (i, source) in PlaybackSource::all().enumerate() {
+            assert_eq!(u16::from(source), i as u16);
+        }
+    }
+
+    #[test]
+    fn playback_source_to_disk() {
+        use PlaybackSource::*;
+        for (source, disk) in [
+            (UDisk, Some(Disk::UDisk)),
+            (Tf, Some(Disk::Tf)),
+            (Aux, None),
+            (Sleep, None),
+            (Flash, Some(Disk::Flash)),
+        ] {
+            assert_eq!(source.to_disk(), disk);
+            if let Some(disk) = disk {
+                assert_eq!(disk.to_playback_source(), Some(source));
+            }
+        }
+    }
+
+    #[test]
+    fn param_enum_ordering() {
+        use EqMode::*;
+        let eq = [Normal, Pop, Rock, Jazz, Classic, Bass];
+        assert!(eq.windows(2).all(|w| w[0] < w[1]));
+        assert!(Rock < Jazz);
+        assert_eq!(eq.iter().max(), Some(&Bass));
+
+        use PlaybackMode::*;
+        let modes = [Repeat, FolderRepeat, SingleRepeat, Random];
+        assert!(modes.windows(2).all(|w| w[0] < w[1]));
+
+        use PlaybackSource::*;
+        let mut sources = [Flash, Sleep, Aux, Tf, UDisk];
+        sources.sort();
+        assert_eq!(sources, [UDisk, Tf, Aux, Sleep, Flash]);
+        for pair in sources.windows(2) {
+            assert!(u16::from(pair[0]) < u16::from(pair[1]));
+        }
+    }
+
+    #[test]
+    fn per_source_queries() {
+        use Storage::*;
+        for (storage, file_count, current_track) in
+            [(Tf, 0x47, 0x4b), (UDisk, 0x48, 0x4c), (Flash, 0x49, 0x4d)]
+        {
+            let source = storage.to_playback_source();
+            assert_eq!(source.to_storage(), Some(storage));
+            assert_eq!(PlaybackSource::from(storage), source);
+            assert_eq!(storage.to_string(), source.to_string());
+
+            let cmd = Command::GetFileCount(storage);
+            assert_eq!(cmd.command_byte(), file_count);
+            assert_eq!(Command::parse(file_count, 0, 0), Ok(cmd));
+            assert!(cmd.is_query());
+            assert!(!cmd.has_parameter());
+
+            let cmd = Command::GetCurrentTrack(storage);
+            assert_eq!(cmd.command_byte(), current_track);
+            assert_eq!(Command::parse(current_track, 0, 0), Ok(cmd));
+            assert!(cmd.is_query());
+            assert!(!cmd.has_parameter());
+        }
+        assert_eq!(
+            Command::GetFileCount(UDisk).to_string(),
+            "Query USB disk file count"
+        );
+        assert_eq!(
+            Command::GetCurrentTrack(Tf).to_string(),
+            "Query SD card current track"
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn deprecated_queries() {
+        use Storage::*;
+        for (old, new) in [
+            (Command::GetTfFileCount, Command::GetFileCount(Tf)),
+            (Command::GetUDiskFileCount, Command::GetFileCount(UDisk)),
+            (Command::GetFlashFileCount, Command::GetFileCount(Flash)),
+            (Command::GetTfCurrentTrack, Command::GetCurrentTrack(Tf)),
+            (
+                Command::GetUDiskCurrentTrack,
+                Command::GetCurrentTrack(UDisk),
+            ),
+            (
+                Command::GetFlashCurrentTrack,
+                Command::GetCurrentTrack(Flash),
+            ),
+        ] {
+            assert_eq!(old, new);
+            assert!(old.is_query());
+            assert_eq!(Command::try_from(old.to_bytes()), Ok(old));
+            // the constants can still be used as patterns
+            assert!(matches!(
+                new,
+                Command::GetTfFileCount
+                    | Command::GetUDiskFileCount
+                    | Command::GetFlashFileCount
+                    | Command::GetTfCurrentTrack
+                    | Command::GetUDiskCurrentTrack
+                    | Command::GetFlashCurrentTrack
+            ));
+        }
+    }
+
+    #[test]
+    fn query_commands() {
+        use Command::*;
+        let queries = [
+            (GetStatus, 0x42),
+            (GetVolume, 0x43),
+            (GetEq, 0x44),
+            (GetPlaybackMode, 0x45),
+            (GetSoftwareVersion, 0x46),
+            (GetFileCount(Storage::Tf), 0x47),
+            (GetFileCount(Storage::UDisk), 0x48),
+            (GetFileCount(Storage::Flash), 0x49),
+            (GetCurrentTrack(Storage::Tf), 0x4b),
+            (GetCurrentTrack(Storage::UDisk), 0x4c),
+            (GetCurrentTrack(Storage::Flash), 0x4d),
+        ];
+        for cmd in ONE_OF_EACH {
+            let expected = queries
+                .iter()
+                .find(|(query, _)| query == cmd)
+                .map(|(_, byte)| *byte);
+            assert_eq!(cmd.is_query(), expected.is_some(), "{cmd:?}");
+            assert_eq!(cmd.expected_response_cmd(), expected, "{cmd:?}");
+        }
+    }
+
+    #[test]
+    fn param_enum_conversions() {
+        use EqMode::*;
+        for (value, eq) in [Normal, Pop, Rock, Jazz, Classic, Bass]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(u16::from(eq), value as u16);
+            assert_eq!(EqMode::try_from(value as u16), Ok(eq));
+        }
+        use PlaybackMode::*;
+        for (value, mode) in [Repeat, FolderRepeat, SingleRepeat, Random]
+            .into_iter()
+            .enumerate()
+        {
+            assert_eq!(u16::from(mode), value as u16);
+            assert_eq!(PlaybackMode::try_from(value as u16), Ok(mode));
+        }
+        use PlaybackSource::*;
+        for (value, source) in
+            [UDisk, Tf, Aux, Sleep, Flash].into_iter().enumerate()
+        {
+            assert_eq!(u16::from(source), value as u16);
+            assert_eq!(PlaybackSource::try_from(value as u16), Ok(source));
+        }
+
+        for value in [0x06, 0xab, 0x100] {
+            assert_eq!(
+                EqMode::try_from(value),
+                Err(Error::InvalidParameterValue(value))
+            );
+        }
+        for value in [0x04, 0xffff] {
+            assert_eq!(
+                PlaybackMode::try_from(value),
+                Err(Error::InvalidParameterValue(value))
+            );
+        }
+        for value in [0x05, 0x100] {
+            assert_eq!(
+                PlaybackSource::try_from(value),
+                Err(Error::InvalidParameterValue(value))
+            );
+        }
+    }
+
     #[test]
     fn serialise_command_track() {
         let mut buf = [0; 16];
@@ -305,6 +1821,159 @@ mod test {
         assert_eq!(&buf[..len], expected);
     }
 
+    #[test]
+    fn to_bytes() {
+        let mut buf = [0u8; 10];
+        for cmd in ONE_OF_EACH {
+            for ack in [RequestAck::No, RequestAck::Yes] {
+                cmd.serialise_with_ack(&mut buf, ack).unwrap();
+                assert_eq!(cmd.to_bytes_with_ack(ack), buf);
+            }
+            assert_eq!(cmd.to_bytes(), cmd.to_bytes_with_ack(RequestAck::No));
+        }
+    }
+
+    #[test]
+    fn request_ack_from_bool() {
+        let mut with_bool = [0u8; 10];
+        let mut with_enum = [0u8; 10];
+        for (flag, ack) in [(false, RequestAck::No), (true, RequestAck::Yes)] {
+            assert_eq!(RequestAck::from(flag), ack);
+            assert_eq!(bool::from(ack), flag);
+            Command::Track(7)
+                .serialise_with_ack(&mut with_bool, flag)
+                .unwrap();
+            Command::Track(7)
+                .serialise_with_ack(&mut with_enum, ack)
+                .unwrap();
+            assert_eq!(with_bool, with_enum);
+        }
+        assert_eq!(RequestAck::No.to_string(), "No ACK");
+        assert_eq!(RequestAck::Yes.to_string(), "ACK requested");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn serialise_into_vec() {
+        let cmd = Command::Track(1);
+        let vec = cmd.serialise_into_vec();
+        assert_eq!(vec.len(), 10);
+        assert_eq!(
+            vec,
+            [0x7e, 0xff, 0x06, 0x03, 0x00, 0x00, 0x01, 0xfe, 0xf7, 0xef]
+        );
+        let vec = cmd.serialise_into_vec_with_ack(RequestAck::Yes);
+        assert_eq!(vec, cmd.to_bytes_with_ack(RequestAck::Yes));
+
+        let all = Command::serialise_all_into_vec(ONE_OF_EACH);
+        assert_eq!(all.len(), ONE_OF_EACH.len() * 10);
+        for (packet, cmd) in all.chunks(10).zip(ONE_OF_EACH) {
+            assert_eq!(packet, cmd.to_bytes());
+        }
+        assert!(Command::serialise_all_into_vec(&[]).is_empty());
+    }
+
+    #[test]
+    fn try_from_command_byte() {
+        for cmd in ONE_OF_EACH {
+            let byte = cmd.command_byte();
+            if cmd.has_parameter() {
+                assert_eq!(
+                    Command::try_from(byte),
+                    Err(Error::CommandRequiresParameter(byte))
+                );
+            } else {
+                assert_eq!(Command::try_from(byte), Ok(*cmd));
+            }
+        }
+        assert_eq!(Command::try_from(0x01), Ok(Command::Next));
+        assert_eq!(Command::try_from(0x0c), Ok(Command::Reset));
+        for byte in [0x03, 0x06, 0x0f] {
+            assert_eq!(
+                Command::try_from(byte),
+                Err(Error::CommandRequiresParameter(byte))
+            );
+        }
+        assert_eq!(Command::try_from(0x00), Err(Error::InvalidCommand(0x00)));
+        assert_eq!(Command::try_from(0xff), Err(Error::InvalidCommand(0xff)));
+    }
+
+    #[test]
+    fn serialise_all() {
+        use Command::*;
+        let mut buf = [0u8; 35];
+        assert_eq!(Command::serialise_all(&[], &mut buf), Ok(0));
+        assert_eq!(Command::serialise_all(&[], &mut []), Ok(0));
+
+        assert_eq!(Command::serialise_all(&[Reset], &mut buf), Ok(10));
+        assert_eq!(buf[..10], Reset.to_bytes());
+
+        let cmds = [Reset, SetVolume(20), Playback];
+        buf.fill(0);
+        assert_eq!(Command::serialise_all(&cmds, &mut buf), Ok(30));
+        for (packet, cmd) in buf.chunks(10).zip(&cmds) {
+            assert_eq!(packet, cmd.to_bytes());
+        }
+        // the rest of the buffer is untouched
+        assert_eq!(buf[30..], [0; 5]);
+
+        let cmds = [
+            (Reset, RequestAck::No),
+            (SetVolume(20), RequestAck::Yes),
+            (Playback, RequestAck::Yes),
+        ];
+        assert_eq!(Command::serialise_all_with_ack(&cmds, &mut buf), Ok(30));
+        for (packet, (cmd, ack)) in buf.chunks(10).zip(&cmds) {
+            assert_eq!(packet, cmd.to_bytes_with_ack(*ack));
+        }
+
+        let mut short = [0u8; 29];
+        assert_eq!(
+            Command::serialise_all(&[Reset, Next, Previous], &mut short),
+            Err(Error::BufferTooShort)
+        );
+        assert_eq!(
+            Command::serialise_all_with_ack(&cmds, &mut short),
+            Err(Error::BufferTooShort)
+        );
+        assert_eq!(short, [0; 29]);
+    }
+
+    #[test]
+    fn serialise_command_custom() {
+        let mut custom = [0; 10];
+        let mut next = [0; 10];
+        Command::Custom {
+            cmd: 0x01,
+            param: 0,
+        }
+        .serialise(&mut custom)
+        .unwrap();
+        Command::Next.serialise(&mut next).unwrap();
+        assert_eq!(custom, next);
+
+        let cmd = Command::Custom {
+            cmd: 0x5a,
+            param: 0x1234,
+        };
+        let mut buf = [0; 10];
+        cmd.serialise(&mut buf).unwrap();
+        let expected: &[u8] = &[
+            0x7e, // START
+            0xff, // VERSION
+            0x06, // LEN
+            0x5a, // command
+            0x00, // request ack
+            0x12, // param high
+            0x34, // param low
+            0xfe, // checksum high
+            0x5b, // checksum low
+            0xef, // STOP
+        ];
+        assert_eq!(buf, expected);
+        assert_eq!(Command::try_from(buf), Err(Error::InvalidCommand(0x5a)));
+    }
+
     #[test]
     fn serialise_command_norflash() {
         let mut buf = [0; 16];
@@ -326,4 +1995,152 @@ mod test {
         eprintln!("< calculated / expected >");
         assert_eq!(&buf[..len], expected);
     }
+
+    #[test]
+    fn serialise_command_bytes() {
+        use Command::*;
+        // START, VERSION, LEN, command, request ack, param high, param
+        // low, checksum high, checksum low, STOP
+        let packets = [
+            (
+                AdvertiseTrack(1),
+                [0x7e, 0xff, 0x06, 0x13, 0x00, 0x00, 0x01, 0xfe, 0xe7, 0xef],
+            ),
+            (
+                AdvertiseFolder {
+                    folder: 2,
+                    track: 5,
+                },
+                [0x7e, 0xff, 0x06, 0x14, 0x00, 0x02, 0x05, 0xfe, 0xe0, 0xef],
+            ),
+            (
+                Command::try_set_folder_track(3, 999).unwrap(),
+                [0x7e, 0xff, 0x06, 0x14, 0x00, 0x33, 0xe7, 0xfd, 0xcd, 0xef],
+            ),
+            (
+                StopAdvertise,
+                [0x7e, 0xff, 0x06, 0x15, 0x00, 0x00, 0x00, 0xfe, 0xe6, 0xef],
+            ),
+            (
+                Stop,
+                [0x7e, 0xff, 0x06, 0x16, 0x00, 0x00, 0x00, 0xfe, 0xe5, 0xef],
+            ),
+            (
+                LoopFolder(2),
+                [0x7e, 0xff, 0x06, 0x17, 0x00, 0x00, 0x02, 0xfe, 0xe2, 0xef],
+            ),
+            (
+                RandomPlay,
+                [0x7e, 0xff, 0x06, 0x18, 0x00, 0x00, 0x00, 0xfe, 0xe3, 0xef],
+            ),
+            (
+                LoopCurrentTrack(true),
+                [0x7e, 0xff, 0x06, 0x19, 0x00, 0x00, 0x00, 0xfe, 0xe2, 0xef],
+            ),
+            (
+                LoopCurrentTrack(false),
+                [0x7e, 0xff, 0x06, 0x19, 0x00, 0x00, 0x01, 0xfe, 0xe1, 0xef],
+            ),
+            (
+                EnableDac(true),
+                [0x7e, 0xff, 0x06, 0x1a, 0x00, 0x00, 0x00, 0xfe, 0xe1, 0xef],
+            ),
+            (
+                EnableDac(false),
+                [0x7e, 0xff, 0x06, 0x1a, 0x00, 0x00, 0x01, 0xfe, 0xe0, 0xef],
+            ),
+        ];
+        for (cmd, expected) in packets {
+            assert_eq!(cmd.to_bytes(), expected, "{cmd:?}");
+        }
+    }
+
+    #[test]
+    fn parse_loop_folder() {
+        assert_eq!(
+            Command::parse(0x17, 0x00, 0x02),
+            Ok(Command::LoopFolder(2))
+        );
+        assert_eq!(
+            Command::parse(0x17, 0x01, 0x02),
+            Err(Error::InvalidParameterValue(0x0102))
+        );
+    }
+
+    #[test]
+    fn set_folder_track_param() {
+        for (folder, track, param) in
+            [(0, 0, 0x0000), (3, 999, 0x33e7), (15, 999, 0xf3e7)]
+        {
+            let cmd = Command::try_set_folder_track(folder, track).unwrap();
+            assert_eq!(cmd.param(), param);
+        }
+        assert_eq!(
+            Command::try_set_folder_track(3, 42).unwrap().to_string(),
+            "Play folder 03, track 0042"
+        );
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn serde_round_trip() {
+        use crate::assert_serde_round_trip;
+        use Command::*;
+
+        let eq_modes = [
+            EqMode::Normal,
+            EqMode::Pop,
+            EqMode::Rock,
+            EqMode::Jazz,
+            EqMode::Classic,
+            EqMode::Bass,
+        ];
+        let playback_modes = [
+            PlaybackMode::Repeat,
+            PlaybackMode::FolderRepeat,
+            PlaybackMode::SingleRepeat,
+            PlaybackMode::Random,
+        ];
+        let sources = [
+            PlaybackSource::UDisk,
+            PlaybackSource::Tf,
+            PlaybackSource::Aux,
+            PlaybackSource::Sleep,
+            PlaybackSource::Flash,
+        ];
+
+        for &cmd in ONE_OF_EACH {
+            assert_serde_round_trip(cmd);
+        }
+        for cmd in [
+            Track(0),
+            Track(2999),
+            RepeatPlay(false),
+            LoopCurrentTrack(false),
+            EnableDac(false),
+            Custom {
+                cmd: 0x5a,
+                param: 0x1234,
+            },
+        ] {
+            assert_serde_round_trip(cmd);
+        }
+        for mode in eq_modes {
+            assert_serde_round_trip(mode);
+            assert_serde_round_trip(SetEq(mode));
+        }
+        for mode in playback_modes {
+            assert_serde_round_trip(mode);
+            assert_serde_round_trip(SetPlaybackMode(mode));
+        }
+        for source in sources {
+            assert_serde_round_trip(source);
+            assert_serde_round_trip(SetPlaybackSource(source));
+        }
+        assert_serde_round_trip(RequestAck::No);
+        assert_serde_round_trip(RequestAck::Yes);
+
+        assert_eq!(serde_json::to_string(&Reset).unwrap(), r#""Reset""#);
+        assert_eq!(serde_json::to_string(&Track(1)).unwrap(), r#"{"Track":1}"#);
+    }
 }
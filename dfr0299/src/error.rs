@@ -6,34 +6,237 @@ use core::fmt::{self, Display, Formatter};
 
 /// Error states for dfr0299. Includes errors for both serialisation
 /// parsing
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
 #[non_exhaustive]
 pub enum Error {
     /// Serialisation buffer should be at least 10 bytes long
     BufferTooShort,
-    /// A complete packet was received but its checksum was invalid
-    BadChecksum,
+    /// The first byte of a packet was not `START`
+    BadStart,
+    /// The version field of a packet was not `VERSION`
+    BadVersion,
+    /// The length field of a packet was not 6
+    BadLength,
+    /// The last byte of a packet was not `STOP`
+    BadStop,
+    /// A complete packet was received but its checksum was invalid.
+    /// Both checksums are big-endian.
+    BadChecksum {
+        /// Checksum calculated from the packet's contents
+        expected: [u8; 2],
+        /// Checksum contained in the packet
+        received: [u8; 2],
+    },
     /// Command not recognised, and the raw value is returned
     InvalidCommand(u8),
+    /// The command with this command byte takes a parameter, so it
+    /// can't be constructed from the command byte alone
+    CommandRequiresParameter(u8),
+    /// The command with this command byte is not a query, so the device
+    /// won't reply to it
+    NotAQuery(u8),
     /// An attempt to parse a parameter value into one of the parameter
-    /// enums failed
-    InvalidParameterValue,
+    /// enums failed, and the raw value is returned
+    InvalidParameterValue(u16),
+    /// The underlying serial peripheral returned an error while
+    /// writing a packet
+    SerialWrite,
+    /// The underlying serial peripheral returned an error while
+    /// reading
+    SerialRead,
+    /// The underlying `embedded_io` writer returned an error, or the
+    /// receiving end of a channel was closed
+    IoWrite,
+    /// The underlying reader returned an error or reached the end of
+    /// its input
+    IoRead,
+    /// The serial port could not be opened
+    SerialOpen,
+    /// Timed out waiting for a response from the device
+    Timeout,
+    /// Volume should be in the range 0-30
+    VolumeOutOfRange,
+    /// Track numbers should be in the range 1-2999, and the invalid
+    /// value is returned
+    TrackOutOfRange(u16),
+    /// Folder numbers should be in the range 1-99 (or 0-15 for
+    /// `Command::SetFolderTrack`), and the invalid value is returned
+    FolderOutOfRange(u16),
+    /// File numbers within a folder should be in the range 1-255, and
+    /// the invalid value is returned
+    FileOutOfRange(u8),
+    /// The gain of `Command::SetVolumeAdjust` should be in the range
+    /// 0-31, and the invalid value is returned
+    GainOutOfRange(u8),
+    /// The requested operation is not valid in the current playback
+    /// state, e.g. resuming when playback is not paused
+    InvalidState,
+    /// Attempted to push onto a command queue which is already at
+    /// capacity
+    QueueFull,
+    /// Received bytes were dropped because a receive buffer was full
+    BufferOverflow,
+    /// A string did not match the name of any parameter value
+    UnknownName,
 }
 
-impl<T: num_enum::TryFromPrimitive> From<num_enum::TryFromPrimitiveError<T>>
-    for Error
+/// Framing or checksum problems found in a complete packet by
+/// `validate_packet`. These are converted into the `Error` variants of
+/// the same names.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum PacketError {
+    /// The first byte of the packet was not `START`
+    BadStart,
+    /// The version field of the packet was not `VERSION`
+    BadVersion,
+    /// The length field of the packet was not 6
+    BadLength,
+    /// The last byte of the packet was not `STOP`
+    BadStop,
+    /// The checksum of the packet was invalid. Both checksums are
+    /// big-endian.
+    BadChecksum {
+        /// Checksum calculated from the packet's contents
+        expected: [u8; 2],
+        /// Checksum contained in the packet
+        received: [u8; 2],
+    },
+}
+
+impl From<PacketError> for Error {
+    fn from(err: PacketError) -> Self {
+        match err {
+            PacketError::BadStart => Self::BadStart,
+            PacketError::BadVersion => Self::BadVersion,
+            PacketError::BadLength => Self::BadLength,
+            PacketError::BadStop => Self::BadStop,
+            PacketError::BadChecksum { expected, received } => {
+                Self::BadChecksum { expected, received }
+            }
+        }
+    }
+}
+
+impl Display for PacketError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        Error::from(*self).fmt(fmt)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PacketError {}
+
+impl<T> From<num_enum::TryFromPrimitiveError<T>> for Error
+where
+    T: num_enum::TryFromPrimitive<Primitive = u8>,
 {
-    fn from(_: num_enum::TryFromPrimitiveError<T>) -> Self {
-        Self::InvalidParameterValue
+    fn from(err: num_enum::TryFromPrimitiveError<T>) -> Self {
+        Self::InvalidParameterValue(err.number.into())
     }
 }
 
 impl Display for Error {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
-        write!(fmt, "{self:?}")
+        match self {
+            Self::BadChecksum { expected, received } => write!(
+                fmt,
+                "BadChecksum: expected {:02x}{:02x}, received {:02x}{:02x}",
+                expected[0], expected[1], received[0], received[1]
+            ),
+            Self::InvalidParameterValue(value) => {
+                write!(fmt, "InvalidParameterValue({value:#06x})")
+            }
+            _ => write!(fmt, "{self:?}"),
+        }
     }
 }
 
 #[cfg(feature = "std")]
 impl std::error::Error for Error {}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        use embedded_io::ErrorKind;
+        match self {
+            Self::QueueFull | Self::BufferOverflow => ErrorKind::OutOfMemory,
+            Self::Timeout => ErrorKind::TimedOut,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn packet_error_conversion() {
+        let checksum = PacketError::BadChecksum {
+            expected: [0xfe, 0xbf],
+            received: [0x00, 0x00],
+        };
+        assert_eq!(
+            Error::from(checksum),
+            Error::BadChecksum {
+                expected: [0xfe, 0xbf],
+                received: [0x00, 0x00],
+            }
+        );
+        assert_eq!(Error::from(PacketError::BadStop), Error::BadStop);
+        assert_eq!(
+            checksum.to_string(),
+            "BadChecksum: expected febf, received 0000"
+        );
+        assert_eq!(PacketError::BadStart.to_string(), "BadStart");
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn serde_round_trip() {
+        use super::Error::*;
+        for err in [
+            BufferTooShort,
+            BadStart,
+            BadVersion,
+            BadLength,
+            BadStop,
+            BadChecksum {
+                expected: [0xfe, 0xbf],
+                received: [0x00, 0x00],
+            },
+            InvalidCommand(0x00),
+            InvalidCommand(0xff),
+            CommandRequiresParameter(0x03),
+            NotAQuery(0x01),
+            InvalidParameterValue(0x00),
+            InvalidParameterValue(0xab),
+            InvalidParameterValue(0xffff),
+            SerialWrite,
+            SerialRead,
+            IoWrite,
+            IoRead,
+            SerialOpen,
+            Timeout,
+            VolumeOutOfRange,
+            TrackOutOfRange(0),
+            TrackOutOfRange(3000),
+            FolderOutOfRange(100),
+            FileOutOfRange(0),
+            GainOutOfRange(32),
+            InvalidState,
+            QueueFull,
+            BufferOverflow,
+            UnknownName,
+        ] {
+            crate::assert_serde_round_trip(err);
+        }
+        crate::assert_serde_round_trip(PacketError::BadLength);
+    }
+}
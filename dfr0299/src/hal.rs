@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `embedded-hal` 1.0 serial peripherals.
+//!
+//! The serial traits were split out of `embedded-hal` 1.0 into the
+//! `embedded-hal-nb` crate, so the bounds here are on
+//! `embedded_hal_nb::serial::Write<u8>`, which is what HALs such as
+//! `rp2040-hal` implement for their UART peripherals.
+
+use crate::proto::PACKET_LEN;
+use crate::{Command, Error, RequestAck, Result};
+use embedded_hal_nb::serial::Write;
+
+/// Serialise a command and write it to the serial peripheral, blocking
+/// until all of the bytes have been accepted
+pub fn send_command<S: Write<u8>>(serial: &mut S, cmd: &Command) -> Result<()> {
+    send_command_with_ack(serial, cmd, RequestAck::No)
+}
+
+/// Serialise a command with the given ACK flag and write it to the
+/// serial peripheral, blocking until all of the bytes have been
+/// accepted
+pub fn send_command_with_ack<S: Write<u8>>(
+    serial: &mut S,
+    cmd: &Command,
+    request_ack: impl Into<RequestAck>,
+) -> Result<()> {
+    let mut buf = [0u8; PACKET_LEN];
+    cmd.serialise_with_ack(&mut buf, request_ack)?;
+    for byte in buf {
+        nb::block!(serial.write(byte)).map_err(|_| Error::SerialWrite)?;
+    }
+    Ok(())
+}
+
+/// Non-blocking command writer, for use in interrupt handlers or RTIC
+/// tasks where the serial peripheral may not be able to accept the
+/// whole packet at once. Each call to `CommandWriter::poll` writes as
+/// many bytes as the peripheral will accept.
+///
+/// ## Example
+/// ```no_run
+/// # fn a<S: embedded_hal_nb::serial::Write<u8>>(uart: S) {
+/// use dfr0299::{hal::CommandWriter, Command};
+/// let mut writer = CommandWriter::new(uart, Command::Track(1));
+/// nb::block!(writer.poll()).unwrap();
+/// let uart = writer.into_inner();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct CommandWriter<S> {
+    serial: S,
+    buf: [u8; PACKET_LEN],
+    pos: usize,
+}
+
+impl<S: Write<u8>> CommandWriter<S> {
+    /// Prepare to write the given command to the serial peripheral.
+    /// Nothing is written until `CommandWriter::poll` is called.
+    pub fn new(serial: S, cmd: Command) -> Self {
+        let mut buf = [0u8; PACKET_LEN];
+        // the buffer is always long enough, so this cannot fail
+        let _ = cmd.serialise(&mut buf);
+        Self {
+            serial,
+            buf,
+            pos: 0,
+        }
+    }
+
+    /// Write as many of the remaining bytes as possible. Returns
+    /// `nb::Error::WouldBlock` until the whole packet has been written,
+    /// after which it returns `Ok(())`.
+    pub fn poll(&mut self) -> nb::Result<(), Error> {
+        while let Some(&byte) = self.buf.get(self.pos) {
+            self.serial
+                .write(byte)
+                .map_err(|e| e.map(|_| Error::SerialWrite))?;
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Release the serial peripheral
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use embedded_hal_nb::serial::{ErrorKind, ErrorType};
+    use pretty_assertions::assert_eq;
+
+    const TRACK_1: [u8; 10] =
+        [0x7e, 0xff, 0x06, 0x03, 0x00, 0x00, 0x01, 0xfe, 0xf7, 0xef];
+
+    /// Records written bytes, returning `WouldBlock` on every other
+    /// write if `busy` is set
+    #[derive(Default)]
+    struct FakeSerial {
+        written: Vec<u8>,
+        busy: bool,
+        blocked: bool,
+    }
+
+    impl ErrorType for FakeSerial {
+        type Error = ErrorKind;
+    }
+
+    impl Write<u8> for FakeSerial {
+        fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+            if self.busy {
+                self.blocked = !self.blocked;
+                if self.blocked {
+                    return Err(nb::Error::WouldBlock);
+                }
+            }
+            self.written.push(word);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn send_command_writes_packet() {
+        let mut serial = FakeSerial {
+            busy: true,
+            ..Default::default()
+        };
+        send_command(&mut serial, &Command::Track(1)).unwrap();
+        assert_eq!(serial.written, TRACK_1);
+    }
+
+    #[test]
+    fn send_command_with_ack_sets_feedback() {
+        let mut serial = FakeSerial::default();
+        send_command_with_ack(&mut serial, &Command::Track(1), RequestAck::Yes)
+            .unwrap();
+        let expected: &[u8] =
+            &[0x7e, 0xff, 0x06, 0x03, 0x01, 0x00, 0x01, 0xfe, 0xf6, 0xef];
+        assert_eq!(serial.written, expected);
+    }
+
+    #[test]
+    fn command_writer_polls_until_done() {
+        let serial = FakeSerial {
+            busy: true,
+            ..Default::default()
+        };
+        let mut writer = CommandWriter::new(serial, Command::Track(1));
+        let mut polls = 0;
+        while let Err(nb::Error::WouldBlock) = writer.poll() {
+            polls += 1;
+        }
+        assert_eq!(polls, 10);
+        assert_eq!(writer.into_inner().written, TRACK_1);
+    }
+}
@@ -0,0 +1,409 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `embedded-hal` 0.2 serial peripherals, for HALs
+//! which haven't moved to `embedded-hal-nb` yet.
+
+use crate::proto::PACKET_LEN;
+use crate::{
+    Command, Error, ParseResult, Parser, RequestAck, Response, Result,
+};
+use embedded_hal_02::serial::{Read, Write};
+
+/// Serialise a command and write it to the serial peripheral, blocking
+/// until all of the bytes have been accepted
+pub fn write_command<S: Write<u8>>(
+    serial: &mut S,
+    cmd: &Command,
+) -> Result<()> {
+    write_command_with_ack(serial, cmd, RequestAck::No)
+}
+
+/// Serialise a command with the given ACK flag and write it to the
+/// serial peripheral, blocking until all of the bytes have been
+/// accepted
+pub fn write_command_with_ack<S: Write<u8>>(
+    serial: &mut S,
+    cmd: &Command,
+    ack: impl Into<RequestAck>,
+) -> Result<()> {
+    let mut buf = [0u8; PACKET_LEN];
+    cmd.serialise_with_ack(&mut buf, ack)?;
+    for byte in buf {
+        nb::block!(serial.write(byte)).map_err(|_| Error::SerialWrite)?;
+    }
+    Ok(())
+}
+
+/// Non-blocking command writer for `embedded-hal` 0.2 serial
+/// peripherals, e.g. for interrupt-driven transmission in RTIC
+/// firmware. Each call to `NbCommandWriter::poll` writes as many bytes
+/// as the peripheral will accept.
+///
+/// ## Example
+/// ```no_run
+/// # fn a<S: embedded_hal_02::serial::Write<u8>>(uart: S) {
+/// use dfr0299::{hal_0_2::NbCommandWriter, Command, RequestAck};
+/// let mut writer =
+///     NbCommandWriter::new(uart, Command::Track(1), RequestAck::No);
+/// nb::block!(writer.poll()).unwrap();
+/// let uart = writer.into_inner();
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct NbCommandWriter<S> {
+    serial: S,
+    buf: [u8; PACKET_LEN],
+    pos: usize,
+}
+
+impl<S: Write<u8>> NbCommandWriter<S> {
+    /// Prepare to write the given command to the serial peripheral.
+    /// Nothing is written until `NbCommandWriter::poll` is called.
+    pub fn new(serial: S, cmd: Command, ack: impl Into<RequestAck>) -> Self {
+        let mut buf = [0u8; PACKET_LEN];
+        // the buffer is always long enough, so this cannot fail
+        let _ = cmd.serialise_with_ack(&mut buf, ack);
+        Self {
+            serial,
+            buf,
+            pos: 0,
+        }
+    }
+
+    /// Write as many of the remaining bytes as possible. Returns
+    /// `nb::Error::WouldBlock` until the whole packet has been written,
+    /// after which it returns `Ok(())`.
+    pub fn poll(&mut self) -> nb::Result<(), Error> {
+        while let Some(&byte) = self.buf.get(self.pos) {
+            self.serial
+                .write(byte)
+                .map_err(|e| e.map(|_| Error::SerialWrite))?;
+            self.pos += 1;
+        }
+        Ok(())
+    }
+
+    /// Whether the whole packet has been written
+    pub fn is_done(&self) -> bool {
+        self.pos == PACKET_LEN
+    }
+
+    /// Release the serial peripheral
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+/// Read bytes from the serial peripheral into the parser until it
+/// either runs out of bytes or completes a response
+fn poll_response<S: Read<u8>>(
+    serial: &mut S,
+    parser: &mut Parser,
+) -> nb::Result<Response, Error> {
+    loop {
+        let byte = serial.read().map_err(|e| e.map(|_| Error::SerialRead))?;
+        if let ParseResult::Complete(resp) = parser.process_byte(byte)? {
+            return Ok(resp);
+        }
+    }
+}
+
+/// Sends commands via the transmit half of a split `embedded-hal` 0.2
+/// UART
+#[derive(Debug)]
+pub struct CommandSender<S> {
+    serial: S,
+}
+
+impl<S: Write<u8>> CommandSender<S> {
+    /// Wrap the transmit half of a UART
+    pub fn new(serial: S) -> Self {
+        Self { serial }
+    }
+
+    /// Serialise a command and write it, as with `write_command`
+    pub fn send(&mut self, cmd: Command) -> Result<()> {
+        write_command(&mut self.serial, &cmd)
+    }
+
+    /// Release the serial peripheral
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+/// Receives responses via the receive half of a split `embedded-hal`
+/// 0.2 UART
+#[derive(Debug)]
+pub struct ResponseReceiver<S> {
+    serial: S,
+    parser: Parser,
+}
+
+impl<S: Read<u8>> ResponseReceiver<S> {
+    /// Wrap the receive half of a UART
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            parser: Parser::new(),
+        }
+    }
+
+    /// Read as many bytes as are available. Returns
+    /// `nb::Error::WouldBlock` until a complete response has been
+    /// parsed. A partially received packet is kept between calls.
+    pub fn poll(&mut self) -> nb::Result<Response, Error> {
+        poll_response(&mut self.serial, &mut self.parser)
+    }
+
+    /// The parser which is receiving responses
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Release the serial peripheral
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+/// Sends commands and receives responses via an unsplit `embedded-hal`
+/// 0.2 UART
+///
+/// ## Example
+/// ```no_run
+/// # fn a<S>(uart: S) -> dfr0299::Result<()>
+/// # where S: embedded_hal_02::serial::Read<u8> + embedded_hal_02::serial::Write<u8> {
+/// use dfr0299::{hal_0_2::DfPlayer, Command};
+/// let mut player = DfPlayer::new(uart);
+/// player.send(Command::Track(1))?;
+/// let response = nb::block!(player.recv())?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct DfPlayer<S> {
+    serial: S,
+    parser: Parser,
+}
+
+impl<S: Read<u8> + Write<u8>> DfPlayer<S> {
+    /// Wrap a UART
+    pub fn new(serial: S) -> Self {
+        Self {
+            serial,
+            parser: Parser::new(),
+        }
+    }
+
+    /// Serialise a command and write it, as with `write_command`
+    pub fn send(&mut self, cmd: Command) -> Result<()> {
+        write_command(&mut self.serial, &cmd)
+    }
+
+    /// Receive a response, as with `ResponseReceiver::poll`
+    pub fn recv(&mut self) -> nb::Result<Response, Error> {
+        poll_response(&mut self.serial, &mut self.parser)
+    }
+
+    /// The parser which is receiving responses
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Release the serial peripheral
+    pub fn into_inner(self) -> S {
+        self.serial
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    /// Records written bytes, accepting at most `fifo` bytes between
+    /// calls to `FakeSerial::drain`
+    struct FakeSerial {
+        written: Vec<u8>,
+        fifo: usize,
+        space: usize,
+    }
+
+    impl FakeSerial {
+        fn drain(&mut self) {
+            self.space = self.fifo;
+        }
+    }
+
+    impl Write<u8> for FakeSerial {
+        type Error = ();
+
+        fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+            if self.space == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+            self.space -= 1;
+            self.written.push(word);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn blocking_write() {
+        let mut serial = FakeSerial {
+            written: Vec::new(),
+            fifo: usize::MAX,
+            space: usize::MAX,
+        };
+        write_command(&mut serial, &Command::Track(1)).unwrap();
+        write_command_with_ack(
+            &mut serial,
+            &Command::Track(1),
+            RequestAck::Yes,
+        )
+        .unwrap();
+
+        let expected: &[u8] = &[
+            0x7e, 0xff, 0x06, 0x03, 0x00, 0x00, 0x01, 0xfe, 0xf7, 0xef, //
+            0x7e, 0xff, 0x06, 0x03, 0x01, 0x00, 0x01, 0xfe, 0xf6, 0xef,
+        ];
+        assert_eq!(serial.written, expected);
+    }
+
+    #[test]
+    fn poll_until_done() {
+        let serial = FakeSerial {
+            written: Vec::new(),
+            fifo: 4,
+            space: 0,
+        };
+        let mut writer =
+            NbCommandWriter::new(serial, Command::Track(1), RequestAck::Yes);
+        let mut polls = 0;
+        while let Err(nb::Error::WouldBlock) = writer.poll() {
+            assert!(!writer.is_done());
+            writer.serial.drain();
+            polls += 1;
+        }
+        assert!(writer.is_done());
+        assert_eq!(polls, 3);
+        assert_eq!(writer.poll(), Ok(()));
+
+        let expected: &[u8] =
+            &[0x7e, 0xff, 0x06, 0x03, 0x01, 0x00, 0x01, 0xfe, 0xf6, 0xef];
+        assert_eq!(writer.into_inner().written, expected);
+    }
+
+    #[test]
+    fn write_error() {
+        struct Broken;
+
+        impl Write<u8> for Broken {
+            type Error = ();
+
+            fn write(&mut self, _: u8) -> nb::Result<(), Self::Error> {
+                Err(nb::Error::Other(()))
+            }
+
+            fn flush(&mut self) -> nb::Result<(), Self::Error> {
+                Ok(())
+            }
+        }
+
+        let mut writer =
+            NbCommandWriter::new(Broken, Command::Next, RequestAck::No);
+        assert_eq!(writer.poll(), Err(nb::Error::Other(Error::SerialWrite)));
+        assert!(!writer.is_done());
+
+        assert_eq!(
+            write_command(&mut Broken, &Command::Next),
+            Err(Error::SerialWrite)
+        );
+    }
+
+    /// Records written bytes and returns queued reads, reporting
+    /// `WouldBlock` once the queue is empty
+    #[derive(Default)]
+    struct FakeUart {
+        written: Vec<u8>,
+        rx: std::collections::VecDeque<nb::Result<u8, ()>>,
+    }
+
+    impl FakeUart {
+        fn receive(&mut self, bytes: &[u8]) {
+            self.rx.extend(bytes.iter().map(|&b| Ok(b)));
+        }
+    }
+
+    impl Read<u8> for FakeUart {
+        type Error = ();
+
+        fn read(&mut self) -> nb::Result<u8, Self::Error> {
+            self.rx.pop_front().unwrap_or(Err(nb::Error::WouldBlock))
+        }
+    }
+
+    impl Write<u8> for FakeUart {
+        type Error = ();
+
+        fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+            self.written.push(word);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sender_and_receiver() {
+        let mut sender = CommandSender::new(FakeUart::default());
+        sender.send(Command::Track(1)).unwrap();
+        assert_eq!(sender.into_inner().written, Command::Track(1).to_bytes());
+
+        let mut receiver = ResponseReceiver::new(FakeUart::default());
+        assert_eq!(receiver.poll(), Err(nb::Error::WouldBlock));
+
+        // a packet split across polls, followed by a second packet
+        let packet = Response::TfFinishPlayback(3).to_bytes();
+        receiver.serial.receive(&packet[..4]);
+        assert_eq!(receiver.poll(), Err(nb::Error::WouldBlock));
+        receiver.serial.receive(&packet[4..]);
+        receiver.serial.receive(&Response::Ack.to_bytes());
+        assert_eq!(receiver.poll(), Ok(Response::TfFinishPlayback(3)));
+        assert_eq!(receiver.poll(), Ok(Response::Ack));
+        assert_eq!(receiver.poll(), Err(nb::Error::WouldBlock));
+        assert_eq!(receiver.parser().stats().bytes_received, 20);
+
+        receiver.serial.rx.push_back(Err(nb::Error::Other(())));
+        assert_eq!(receiver.poll(), Err(nb::Error::Other(Error::SerialRead)));
+    }
+
+    #[test]
+    fn df_player() {
+        let mut player = DfPlayer::new(FakeUart::default());
+        player.send(Command::GetVolume).unwrap();
+        assert_eq!(player.recv(), Err(nb::Error::WouldBlock));
+
+        player.serial.receive(&Response::Ack.to_bytes());
+        assert_eq!(nb::block!(player.recv()), Ok(Response::Ack));
+
+        // parse errors are reported without blocking
+        let mut bad = Response::Ack.to_bytes();
+        bad[8] ^= 0xff;
+        player.serial.receive(&bad);
+        assert!(matches!(
+            player.recv(),
+            Err(nb::Error::Other(Error::BadChecksum { .. }))
+        ));
+        assert_eq!(player.into_inner().written, Command::GetVolume.to_bytes());
+    }
+}
@@ -0,0 +1,121 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Hex formatting of raw packet bytes for diagnostics.
+
+use crate::Command;
+use core::fmt::{self, Display, Formatter, LowerHex, UpperHex};
+
+/// Formats bytes as space-separated lowercase hex pairs, e.g.
+/// `7e ff 06 03 00 00 01 fe f7 ef`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HexDisplay<'a>(pub &'a [u8]);
+
+/// Formats bytes as space-separated uppercase hex pairs, e.g.
+/// `7E FF 06 03 00 00 01 FE F7 EF`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HexDisplayUpper<'a>(pub &'a [u8]);
+
+impl Display for HexDisplay<'_> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for (idx, byte) in self.0.iter().enumerate() {
+            if idx > 0 {
+                fmt.write_str(" ")?;
+            }
+            write!(fmt, "{byte:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for HexDisplayUpper<'_> {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        for (idx, byte) in self.0.iter().enumerate() {
+            if idx > 0 {
+                fmt.write_str(" ")?;
+            }
+            write!(fmt, "{byte:02X}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the serialised packet (without requesting an ACK) as with
+/// `HexDisplay`
+impl LowerHex for Command {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        HexDisplay(&self.to_bytes()).fmt(fmt)
+    }
+}
+
+/// Formats the serialised packet (without requesting an ACK) as with
+/// `HexDisplayUpper`
+impl UpperHex for Command {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        HexDisplayUpper(&self.to_bytes()).fmt(fmt)
+    }
+}
+
+#[cfg(feature = "use_defmt")]
+impl defmt::Format for HexDisplay<'_> {
+    fn format(&self, fmt: defmt::Formatter) {
+        for (idx, byte) in self.0.iter().enumerate() {
+            if idx > 0 {
+                defmt::write!(fmt, " ");
+            }
+            defmt::write!(fmt, "{=u8:02x}", byte);
+        }
+    }
+}
+
+#[cfg(feature = "use_defmt")]
+impl defmt::Format for HexDisplayUpper<'_> {
+    fn format(&self, fmt: defmt::Formatter) {
+        for (idx, byte) in self.0.iter().enumerate() {
+            if idx > 0 {
+                defmt::write!(fmt, " ");
+            }
+            defmt::write!(fmt, "{=u8:02X}", byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn track_packet() {
+        let mut buf = [0u8; 10];
+        Command::Track(1).serialise(&mut buf).unwrap();
+        assert_eq!(
+            HexDisplay(&buf).to_string(),
+            "7e ff 06 03 00 00 01 fe f7 ef"
+        );
+        assert_eq!(
+            HexDisplayUpper(&buf).to_string(),
+            "7E FF 06 03 00 00 01 FE F7 EF"
+        );
+    }
+
+    #[test]
+    fn command_hex() {
+        for (cmd, expected) in [
+            (Command::Track(1), "7e ff 06 03 00 00 01 fe f7 ef"),
+            (Command::Reset, "7e ff 06 0c 00 00 00 fe ef ef"),
+            (Command::SetVolume(20), "7e ff 06 06 00 00 14 fe e1 ef"),
+        ] {
+            assert_eq!(format!("{cmd:x}"), expected);
+            assert_eq!(format!("{cmd:X}"), expected.to_uppercase());
+        }
+    }
+
+    #[test]
+    fn short_inputs() {
+        assert_eq!(HexDisplay(&[]).to_string(), "");
+        assert_eq!(HexDisplay(&[0x0a]).to_string(), "0a");
+        assert_eq!(HexDisplayUpper(&[0x0a, 0xbc]).to_string(), "0A BC");
+    }
+}
@@ -0,0 +1,58 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `embedded-io` writers, as implemented by the UART
+//! drivers in Embassy and similar runtimes.
+
+use crate::{Command, Error, RequestAck, Result};
+use embedded_io::Write;
+
+/// Serialise a command and write the whole packet to the writer
+pub fn send_command<W: Write>(writer: &mut W, cmd: &Command) -> Result<()> {
+    send_command_with_ack(writer, cmd, RequestAck::No)
+}
+
+/// Serialise a command with the given ACK flag and write the whole
+/// packet to the writer
+pub fn send_command_with_ack<W: Write>(
+    writer: &mut W,
+    cmd: &Command,
+    request_ack: impl Into<RequestAck>,
+) -> Result<()> {
+    let mut buf = [0u8; 10];
+    cmd.serialise_with_ack(&mut buf, request_ack)?;
+    writer.write_all(&buf).map_err(|_| Error::IoWrite)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn send_commands() {
+        let mut written = Vec::new();
+        send_command(&mut written, &Command::Track(1)).unwrap();
+        send_command_with_ack(
+            &mut written,
+            &Command::Track(1),
+            RequestAck::Yes,
+        )
+        .unwrap();
+        let expected: &[u8] = &[
+            0x7e, 0xff, 0x06, 0x03, 0x00, 0x00, 0x01, 0xfe, 0xf7, 0xef, //
+            0x7e, 0xff, 0x06, 0x03, 0x01, 0x00, 0x01, 0xfe, 0xf6, 0xef,
+        ];
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn write_error() {
+        let mut buf = [0u8; 5];
+        assert_eq!(
+            send_command(&mut &mut buf[..], &Command::Next),
+            Err(Error::IoWrite)
+        );
+    }
+}
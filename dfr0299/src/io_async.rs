@@ -0,0 +1,328 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `embedded-io-async` readers and writers, for
+//! talking to the device from async tasks e.g. with Embassy.
+
+use crate::{
+    Command, Error, ParseResult, Parser, RequestAck, Response, Result,
+};
+use embedded_io_async::{Read, Write};
+
+/// Serialise a command and write the whole packet to the writer
+pub async fn send_command<W: Write>(
+    writer: &mut W,
+    cmd: &Command,
+) -> Result<()> {
+    send_command_with_ack(writer, cmd, RequestAck::No).await
+}
+
+/// Serialise a command with the given ACK flag and write the whole
+/// packet to the writer. Returns `Error::IoWrite` if the writer returns
+/// an error.
+pub async fn send_command_with_ack<W: Write>(
+    writer: &mut W,
+    cmd: &Command,
+    request_ack: impl Into<RequestAck>,
+) -> Result<()> {
+    let buf = cmd.to_bytes_with_ack(request_ack);
+    writer.write_all(&buf).await.map_err(|_| Error::IoWrite)
+}
+
+/// Async wrapper around `Parser` which reads from an
+/// `embedded_io_async::Read` until a complete message has been parsed.
+///
+/// The reader may return several bytes at once, so any bytes following
+/// a complete message are kept and used on the next call to
+/// `AsyncParser::read_message`. For this reason the same `AsyncParser`
+/// should be reused for every read from a given reader.
+///
+/// ## Example
+/// ```no_run
+/// # async fn a<R: embedded_io_async::Read>(mut uart: R) {
+/// use dfr0299::io_async::AsyncParser;
+/// let mut parser = AsyncParser::new();
+/// loop {
+///     match parser.read_message(&mut uart).await {
+///         Ok(msg) => { /* handle message */ }
+///         Err(e) => { /* handle error */ }
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct AsyncParser {
+    parser: Parser,
+    buf: [u8; 4],
+    pos: usize,
+    len: usize,
+}
+
+impl AsyncParser {
+    /// Initialise a new `AsyncParser`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read from the reader until a complete message has been parsed.
+    /// Returns `Error::IoRead` if the reader returns an error or reaches
+    /// the end of its input, or any parse error produced by
+    /// `Parser::process_byte`.
+    pub async fn read_message<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<Response> {
+        loop {
+            while let Some(&byte) = self.buf[..self.len].get(self.pos) {
+                self.pos += 1;
+                if let ParseResult::Complete(resp) =
+                    self.parser.process_byte(byte)?
+                {
+                    return Ok(resp);
+                }
+            }
+
+            let len = reader
+                .read(&mut self.buf)
+                .await
+                .map_err(|_| Error::IoRead)?;
+            if len == 0 {
+                return Err(Error::IoRead);
+            }
+            self.pos = 0;
+            self.len = len;
+        }
+    }
+}
+
+/// Read from the reader one byte at a time, feeding each byte to the
+/// parser, until a complete message has been parsed. Errors are
+/// returned as with `AsyncParser::read_message`.
+///
+/// Unlike `AsyncParser` this doesn't read ahead, so no bytes are lost
+/// if the reader is then used for something else, at the cost of a
+/// read for every byte.
+pub async fn read_message<R: Read>(
+    reader: &mut R,
+    parser: &mut Parser,
+) -> Result<Response> {
+    let mut buf = [0u8; 1];
+    loop {
+        let len = reader.read(&mut buf).await.map_err(|_| Error::IoRead)?;
+        if len == 0 {
+            return Err(Error::IoRead);
+        }
+        if let ParseResult::Complete(resp) = parser.process_byte(buf[0])? {
+            return Ok(resp);
+        }
+    }
+}
+
+/// Owns an `embedded_io_async::Read` and receives responses from it one
+/// byte at a time, as with `read_message`.
+///
+/// ## Example
+/// ```no_run
+/// # async fn a<R: embedded_io_async::Read>(uart: R) {
+/// use dfr0299::io_async::AsyncResponseReceiver;
+/// let mut receiver = AsyncResponseReceiver::new(uart);
+/// while let Ok(msg) = receiver.recv().await {
+///     // handle message
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct AsyncResponseReceiver<R> {
+    reader: R,
+    parser: Parser,
+}
+
+impl<R: Read> AsyncResponseReceiver<R> {
+    /// Receive responses from the given reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            parser: Parser::new(),
+        }
+    }
+
+    /// Read until a complete response has been parsed. Errors are
+    /// returned as with `AsyncParser::read_message`.
+    pub async fn recv(&mut self) -> Result<Response> {
+        read_message(&mut self.reader, &mut self.parser).await
+    }
+
+    /// The parser which is receiving responses
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Release the reader. Any partially received packet is discarded.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::{Disk, DiskSet};
+    use core::future::Future;
+    use core::pin::pin;
+    use core::task::{Context, Poll, Waker};
+    use embedded_io_async::ErrorType;
+    use pretty_assertions::assert_eq;
+
+    /// Poll a future which never actually waits to completion
+    pub(crate) fn block_on<F: Future>(fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(out) = fut.as_mut().poll(&mut cx) {
+                return out;
+            }
+        }
+    }
+
+    /// Returns `data` in chunks of at most `chunk` bytes per read
+    pub(crate) struct FakeReader<'a> {
+        pub data: &'a [u8],
+        pub chunk: usize,
+    }
+
+    impl ErrorType for FakeReader<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for FakeReader<'_> {
+        async fn read(
+            &mut self,
+            buf: &mut [u8],
+        ) -> core::result::Result<usize, Self::Error> {
+            let len = self.data.len().min(buf.len()).min(self.chunk);
+            let (head, tail) = self.data.split_at(len);
+            buf[..len].copy_from_slice(head);
+            self.data = tail;
+            Ok(len)
+        }
+    }
+
+    /// Accepts at most three bytes per write, and fails once `fail_after`
+    /// bytes have been written
+    struct FakeWriter {
+        written: Vec<u8>,
+        fail_after: usize,
+    }
+
+    impl ErrorType for FakeWriter {
+        type Error = embedded_io_async::ErrorKind;
+    }
+
+    impl Write for FakeWriter {
+        async fn write(
+            &mut self,
+            buf: &[u8],
+        ) -> core::result::Result<usize, Self::Error> {
+            if self.written.len() >= self.fail_after {
+                return Err(embedded_io_async::ErrorKind::Other);
+            }
+            let len = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn send_commands() {
+        let mut writer = FakeWriter {
+            written: Vec::new(),
+            fail_after: 20,
+        };
+        block_on(send_command(&mut writer, &Command::Next)).unwrap();
+        block_on(send_command_with_ack(&mut writer, &Command::Track(1), true))
+            .unwrap();
+        assert_eq!(
+            writer.written,
+            [
+                Command::Next.to_bytes(),
+                Command::Track(1).to_bytes_with_ack(RequestAck::Yes)
+            ]
+            .concat()
+        );
+        assert_eq!(
+            block_on(send_command(&mut writer, &Command::Stop)),
+            Err(Error::IoWrite)
+        );
+    }
+
+    #[test]
+    fn response_receiver() {
+        let data = [
+            Response::DiskInserted(Disk::Tf).to_bytes(),
+            Response::Ack.to_bytes(),
+        ]
+        .concat();
+        let reader = FakeReader {
+            data: &data[..15],
+            chunk: 4,
+        };
+        let mut receiver = AsyncResponseReceiver::new(reader);
+        assert_eq!(
+            block_on(receiver.recv()),
+            Ok(Response::DiskInserted(Disk::Tf))
+        );
+        assert_eq!(block_on(receiver.recv()), Err(Error::IoRead));
+        assert_eq!(receiver.parser().stats().bytes_received, 15);
+        assert!(receiver.into_inner().data.is_empty());
+    }
+
+    #[test]
+    fn read_message_free_function() {
+        let data = [
+            0x00, // noise
+            0x7e, 0xff, 0x06, 0x3d, 0x00, 0x00, 0x03, 0xfe, 0xbb, 0xef, //
+            0x7e, 0xff, 0x06, 0x3d, 0x00, 0x00, 0x04,
+        ];
+        let mut reader = FakeReader {
+            data: &data,
+            chunk: 16,
+        };
+        let mut parser = Parser::new();
+        assert_eq!(
+            block_on(read_message(&mut reader, &mut parser)),
+            Ok(Response::TfFinishPlayback(3))
+        );
+        // the following bytes haven't been read
+        assert_eq!(reader.data, &data[11..]);
+        assert_eq!(
+            block_on(read_message(&mut reader, &mut parser)),
+            Err(Error::IoRead)
+        );
+        assert_eq!(parser.stats().bytes_received, data.len() as u32);
+    }
+
+    #[test]
+    fn read_messages_in_chunks() {
+        let data = [
+            0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef, //
+            0x7e, 0xff, 0x06, 0x3f, 0x00, 0x00, 0x02, 0xfe, 0xba, 0xef,
+        ];
+        for chunk in [1, 2, 3] {
+            let mut reader = FakeReader { data: &data, chunk };
+            let mut parser = AsyncParser::new();
+            assert_eq!(
+                block_on(parser.read_message(&mut reader)),
+                Ok(Response::DiskRemoved(Disk::UDisk))
+            );
+            assert_eq!(
+                block_on(parser.read_message(&mut reader)),
+                Ok(Response::DiskOnline(DiskSet::TF))
+            );
+            assert_eq!(
+                block_on(parser.read_message(&mut reader)),
+                Err(Error::IoRead)
+            );
+        }
+    }
+}
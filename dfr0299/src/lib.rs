@@ -15,10 +15,67 @@
 //! Communication with the module is via UART at 9600-8-N-1.
 //!
 //! ## Features
-//! * `std`: implement `std::error::Error` for `Error`
+//! * `std`: implement `std::error::Error` for `Error` and
+//!   `PacketError`, and add a [`CommandSequence`] for sending scripted
+//!   commands via a `std::io::Write`
 //! * `use_defmt`: All types derive implementations of `defmt::Format`
-//! to allow them to be formatted by `defmt` when used on embedded
-//! devices
+//!   to allow them to be formatted by `defmt` when used on embedded
+//!   devices
+//! * `embedded-hal`: blocking and non-blocking helpers for sending
+//!   commands over an `embedded-hal` 1.0 serial peripheral, see
+//!   [`hal`]
+//! * `embedded-hal-02`: blocking and non-blocking helpers for sending
+//!   commands and receiving responses over `embedded-hal` 0.2 serial
+//!   peripherals, see [`hal_0_2`]
+//! * `embedded-io`: helpers for sending commands via an
+//!   `embedded_io::Write`, see [`io`], and an implementation of
+//!   `embedded_io::Write` for [`Parser`] which keeps the parsed
+//!   responses until `Parser::drain_responses` is called
+//! * `use_serde`: All types implement `serde::Serialize` and
+//!   `serde::Deserialize`. This does not require `std`.
+//! * `use_arbitrary`: All command and response types derive
+//!   implementations of `arbitrary::Arbitrary` for use in fuzz testing
+//!   (implies `std`). A `cargo-fuzz` target for the parser lives in
+//!   the `fuzz` directory.
+//! * `player`: a high-level [`Player`] which tracks the playback state
+//!   of the device, sending commands via an `embedded_io::Write`
+//!   (implies `embedded-io`)
+//! * `heapless`: a fixed-capacity [`CommandQueue`] for batching
+//!   commands without an allocator, a [`PriorityCommandQueue`] for
+//!   commands which must preempt others, a [`QueryTracker`] for matching
+//!   replies to queries, a [`RingParser`] for buffering bytes received
+//!   via DMA, `Parser::extend_collecting`, and
+//!   `to_heapless_string` on `Command` and `Response` (implies
+//!   `embedded-io`)
+//! * `embedded-io-async`: an [`AsyncParser`](io_async::AsyncParser)
+//!   and an [`AsyncResponseReceiver`](io_async::AsyncResponseReceiver)
+//!   for receiving responses from an `embedded_io_async::Read`, and
+//!   helpers for sending commands via an `embedded_io_async::Write`,
+//!   e.g. the UARTs in Embassy, see [`io_async`]
+//! * `log`: emit `log` records for serialised commands (target
+//!   `dfr0299::serialise`) and for parser state transitions, parsed
+//!   responses and errors (target `dfr0299::parser`)
+//! * `tracing`: the same diagnostics as `log` as `tracing` events with
+//!   structured fields, and spans around serialisation and the parser
+//!   methods which process several bytes. This does not require `std`.
+//! * `futures`: a [`StreamParser`](stream::StreamParser) which yields
+//!   responses read from a `futures::io::AsyncRead`, and a
+//!   [`ParserSink`](stream::ParserSink) which sends the responses
+//!   parsed from the bytes pushed into it to a channel, see [`stream`]
+//!   (implies `std`)
+//! * `tokio`: a [`TokioSerial`](tokio_io::TokioSerial) for talking to
+//!   the device via `tokio_serial` from async code, see [`tokio_io`]
+//!   (implies `std`)
+//! * `mio`: a [`MioParser`](mio_io::MioParser) which can be registered
+//!   with a `mio::Poll` to receive responses from a
+//!   `mio_serial::SerialStream` in an event loop, see [`mio_io`]
+//!   (implies `std`)
+//! * `mock`: a [`MockSerial`](mock::MockSerial) which records sent
+//!   commands and returns injected responses, for unit testing code
+//!   without the hardware (implies `std`)
+//! * `test-utils`: helpers for constructing packets and checking
+//!   responses in tests, including on embedded targets with
+//!   `use_defmt`, see [`test_utils`]
 //!
 //! ## Usage - serialisation
 //! This example just demonstrates serialising commands into a buffer.
@@ -52,6 +109,9 @@
 //!        uart.read_exact(&mut buf)?;
 //!        match parser.process_byte(buf[0]) {
 //!            Ok(ParseResult::Incomplete) => {}
+//!            Ok(ParseResult::Ignored(byte)) => {
+//!                println!("Ignored unexpected byte {byte:#04x}");
+//!            }
 //!            Ok(ParseResult::Complete(msg)) => {
 //!                println!("Message received: {msg:?}");
 //!            }
@@ -80,23 +140,79 @@
 //! (excluding START). Note that the example packets in the datasheet
 //! have incorrect checksums.
 
+pub mod commands;
 mod control;
 mod error;
+#[cfg(feature = "embedded-hal")]
+pub mod hal;
+#[cfg(feature = "embedded-hal-02")]
+pub mod hal_0_2;
+mod hex;
+#[cfg(feature = "embedded-io")]
+pub mod io;
+#[cfg(feature = "embedded-io-async")]
+pub mod io_async;
+#[cfg(feature = "mio")]
+pub mod mio_io;
+#[cfg(feature = "mock")]
+pub mod mock;
+mod packet;
+mod params;
 mod parser;
+#[cfg(feature = "player")]
+mod player;
+pub mod proto;
+#[cfg(feature = "heapless")]
+mod queue;
 mod response;
+#[cfg(feature = "heapless")]
+mod ring;
+#[cfg(feature = "std")]
+mod sequence;
+#[cfg(feature = "futures")]
+pub mod stream;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
+#[cfg(feature = "tokio")]
+pub mod tokio_io;
+#[cfg(feature = "heapless")]
+mod tracker;
 
 pub use control::*;
-pub use error::Error;
+pub use error::{Error, PacketError};
+pub use hex::*;
+pub use packet::*;
+pub use params::*;
 pub use parser::*;
+#[cfg(feature = "player")]
+pub use player::*;
+pub use proto::{START, STOP, VERSION};
+#[cfg(feature = "heapless")]
+pub use queue::*;
 pub use response::*;
+#[cfg(feature = "heapless")]
+pub use ring::*;
+#[cfg(feature = "std")]
+pub use sequence::*;
+#[cfg(feature = "heapless")]
+pub use tracker::*;
 
 /// Newtype wrapping this crate's Error
 pub type Result<T> = core::result::Result<T, Error>;
 
-/// Packet start byte
-pub const START: u8 = 0x7e;
-/// Packet end byte
-pub const STOP: u8 = 0xef;
-/// Packet version field. This just seems to be hardcoded to `0xff` and
-/// not actually used for anything
-pub const VERSION: u8 = 0xff;
+/// Check that a value survives a round trip through both a
+/// self-describing (JSON) and a compact binary (postcard) format
+#[cfg(all(test, feature = "use_serde"))]
+pub(crate) fn assert_serde_round_trip<T>(value: T)
+where
+    T: serde::Serialize
+        + serde::de::DeserializeOwned
+        + PartialEq
+        + core::fmt::Debug,
+{
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(serde_json::from_str::<T>(&json).unwrap(), value, "{json}");
+
+    let bytes = postcard::to_allocvec(&value).unwrap();
+    assert_eq!(postcard::from_bytes::<T>(&bytes).unwrap(), value);
+}
@@ -0,0 +1,370 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `mio`, for receiving responses from a
+//! `mio_serial::SerialStream` in an event loop.
+
+use crate::proto::PACKET_LEN;
+use crate::{Command, Error, ParseResult, Parser, Response, Result};
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use mio_serial::{SerialPortBuilderExt, SerialStream};
+use std::io::{ErrorKind, Read, Write};
+
+/// Connection to the device which can be registered with a `mio::Poll`.
+/// This is usually a `mio_serial::SerialStream` opened with
+/// `MioParser::new`, but any non-blocking `Read + Write` source can be
+/// wrapped with `MioParser::from_stream`.
+///
+/// `mio` events are edge-triggered, so on each readable event
+/// `MioParser::try_recv_response` should be called until it returns
+/// `None`, and on each writable event `MioParser::write_pending` should
+/// be called to finish sending a packet which the stream couldn't
+/// accept in one go.
+///
+/// ## Example
+/// ```no_run
+/// # fn a() -> Result<(), Box<dyn std::error::Error>> {
+/// use dfr0299::{mio_io::MioParser, Command};
+/// use mio::{Events, Interest, Poll, Token};
+/// let mut poll = Poll::new()?;
+/// let mut serial = MioParser::new("/dev/ttyUSB0", 9600)?;
+/// poll.registry().register(
+///     &mut serial,
+///     Token(0),
+///     Interest::READABLE | Interest::WRITABLE,
+/// )?;
+/// serial.send(Command::Track(1))?;
+///
+/// let mut events = Events::with_capacity(4);
+/// loop {
+///     poll.poll(&mut events, None)?;
+///     for event in &events {
+///         if event.is_writable() {
+///             serial.write_pending()?;
+///         }
+///     }
+///     while let Some(resp) = serial.try_recv_response() {
+///         println!("{:?}", resp?);
+///     }
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MioParser<S = SerialStream> {
+    stream: S,
+    parser: Parser,
+    buf: [u8; 16],
+    pos: usize,
+    len: usize,
+    /// Packet being sent, of which the last `tx_pending` bytes haven't
+    /// been written yet
+    tx: [u8; PACKET_LEN],
+    tx_pending: usize,
+}
+
+impl MioParser<SerialStream> {
+    /// Open the serial port at `path` in non-blocking mode. The device
+    /// uses a baud rate of 9600. Returns `Error::SerialOpen` if the
+    /// port can't be opened.
+    pub fn new(path: &str, baud: u32) -> Result<Self> {
+        let stream = mio_serial::new(path, baud)
+            .open_native_async()
+            .map_err(|_| Error::SerialOpen)?;
+        Ok(Self::from_stream(stream))
+    }
+}
+
+impl<S: Read + Write> MioParser<S> {
+    /// Wrap an already opened stream
+    pub fn from_stream(stream: S) -> Self {
+        Self {
+            stream,
+            parser: Parser::new(),
+            buf: [0; 16],
+            pos: 0,
+            len: 0,
+            tx: [0; PACKET_LEN],
+            tx_pending: 0,
+        }
+    }
+
+    /// Release the underlying stream. Any bytes which have been read
+    /// but not yet parsed are discarded.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// The parser which is receiving responses
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    /// Send a command without requesting an ACK. If the stream's
+    /// transmit buffer fills up partway through the packet then the
+    /// rest of it is kept and written by `MioParser::write_pending`, so
+    /// the device never sees a truncated packet.
+    ///
+    /// Returns `Error::QueueFull` without sending anything if the
+    /// previous packet still hasn't been written completely, or
+    /// `Error::IoWrite` if the stream returns an error other than
+    /// `ErrorKind::WouldBlock`. In the latter case part of the packet
+    /// may already have been sent.
+    pub fn send(&mut self, cmd: Command) -> Result<()> {
+        if !self.write_pending()? {
+            return Err(Error::QueueFull);
+        }
+        self.tx = cmd.to_bytes();
+        self.tx_pending = PACKET_LEN;
+        self.write_pending().map(|_| ())
+    }
+
+    /// Write the rest of a packet which the stream couldn't accept
+    /// during `MioParser::send`, e.g. on a writable event. Returns
+    /// whether the packet has now been written completely, which is
+    /// also the case if nothing was pending. Returns `Error::IoWrite`
+    /// and discards the rest of the packet if the stream returns an
+    /// error other than `ErrorKind::WouldBlock`.
+    pub fn write_pending(&mut self) -> Result<bool> {
+        while self.tx_pending > 0 {
+            match self.stream.write(&self.tx[PACKET_LEN - self.tx_pending..]) {
+                Ok(0) => {
+                    self.tx_pending = 0;
+                    return Err(Error::IoWrite);
+                }
+                Ok(len) => self.tx_pending -= len,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    return Ok(false)
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.tx_pending = 0;
+                    return Err(Error::IoWrite);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether part of a packet is still waiting to be written by
+    /// `MioParser::write_pending`
+    pub fn has_pending_write(&self) -> bool {
+        self.tx_pending > 0
+    }
+
+    /// Parse the bytes which are available without blocking. Returns
+    /// the next complete response or parse error, or `None` once the
+    /// stream has no more bytes to read. A partially received packet
+    /// is kept for the next call. Returns `Error::IoRead` if the stream
+    /// returns an error or is closed.
+    pub fn try_recv_response(&mut self) -> Option<Result<Response>> {
+        loop {
+            while let Some(&byte) = self.buf[..self.len].get(self.pos) {
+                self.pos += 1;
+                match self.parser.process_byte(byte) {
+                    Ok(ParseResult::Complete(resp)) => return Some(Ok(resp)),
+                    Ok(_) => {}
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+
+            match self.stream.read(&mut self.buf) {
+                Ok(0) => return Some(Err(Error::IoRead)),
+                Ok(len) => {
+                    self.pos = 0;
+                    self.len = len;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return None,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => return Some(Err(Error::IoRead)),
+            }
+        }
+    }
+
+    /// Parse the bytes which are available without blocking, as with
+    /// `MioParser::try_recv_response`, for use with `nb::block!`.
+    /// Returns `nb::Error::WouldBlock` once the stream has no more bytes
+    /// to read.
+    pub fn poll_response(&mut self) -> nb::Result<Response, Error> {
+        match self.try_recv_response() {
+            Some(Ok(resp)) => Ok(resp),
+            Some(Err(err)) => Err(nb::Error::Other(err)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+impl<S: Source> Source for MioParser<S> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> std::io::Result<()> {
+        self.stream.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> std::io::Result<()> {
+        self.stream.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> std::io::Result<()> {
+        self.stream.deregister(registry)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, DiskSet};
+    use pretty_assertions::assert_eq;
+    use std::collections::VecDeque;
+
+    /// Non-blocking stream which returns `WouldBlock` between each
+    /// chunk of queued bytes
+    #[derive(Default)]
+    struct FakeStream {
+        written: Vec<u8>,
+        chunks: VecDeque<std::io::Result<Vec<u8>>>,
+        /// Number of bytes accepted by each write, where 0 means
+        /// `WouldBlock`. Writes are unlimited once this is empty.
+        write_limits: VecDeque<usize>,
+    }
+
+    impl Read for FakeStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let chunk = self
+                .chunks
+                .pop_front()
+                .unwrap_or_else(|| Err(ErrorKind::WouldBlock.into()))?;
+            // the test chunks are no larger than the read buffer
+            buf[..chunk.len()].copy_from_slice(&chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    impl Write for FakeStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            match self.write_limits.pop_front() {
+                Some(0) => Err(ErrorKind::WouldBlock.into()),
+                Some(limit) => self.written.write(&buf[..limit.min(buf.len())]),
+                None => self.written.write(buf),
+            }
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn send() {
+        let mut serial = MioParser::from_stream(FakeStream::default());
+        serial.send(Command::Track(1)).unwrap();
+        assert_eq!(serial.into_inner().written, Command::Track(1).to_bytes());
+    }
+
+    #[test]
+    fn send_partial_packet() {
+        let stream = FakeStream {
+            write_limits: [4, 0, 0, 3].into(),
+            ..FakeStream::default()
+        };
+        let mut serial = MioParser::from_stream(stream);
+
+        // the rest of the packet is kept until the stream is writable
+        serial.send(Command::Track(1)).unwrap();
+        assert!(serial.has_pending_write());
+        assert_eq!(serial.send(Command::Next), Err(Error::QueueFull));
+        assert_eq!(serial.write_pending(), Ok(true));
+        assert!(!serial.has_pending_write());
+        assert_eq!(serial.write_pending(), Ok(true));
+
+        serial.send(Command::Next).unwrap();
+        let expected = [Command::Track(1).to_bytes(), Command::Next.to_bytes()];
+        assert_eq!(serial.into_inner().written, expected.concat());
+    }
+
+    #[test]
+    fn drain_responses() {
+        let online = Response::DiskOnline(DiskSet::TF).to_bytes();
+        let ack = Response::Ack.to_bytes();
+        let mut stream = FakeStream::default();
+        // one packet split across reads, then the tail of a packet and a
+        // whole packet in one read
+        stream.chunks.push_back(Ok(online[..4].to_vec()));
+        stream.chunks.push_back(Err(ErrorKind::Interrupted.into()));
+        stream.chunks.push_back(Ok(online[4..].to_vec()));
+        stream.chunks.push_back(Err(ErrorKind::WouldBlock.into()));
+        stream.chunks.push_back(Ok([&ack[4..], &ack[..]].concat()));
+        let mut serial = MioParser::from_stream(stream);
+
+        assert_eq!(
+            serial.try_recv_response(),
+            Some(Ok(Response::DiskOnline(DiskSet::TF)))
+        );
+        assert_eq!(serial.try_recv_response(), None);
+        // the tail of a packet whose start was missed is skipped
+        assert_eq!(serial.try_recv_response(), Some(Ok(Response::Ack)));
+        assert_eq!(serial.try_recv_response(), None);
+        assert_eq!(serial.parser().stats().bytes_received, 26);
+
+        serial
+            .stream
+            .chunks
+            .push_back(Ok(Response::DiskRemoved(Disk::Tf).to_bytes().into()));
+        assert_eq!(
+            serial.try_recv_response(),
+            Some(Ok(Response::DiskRemoved(Disk::Tf)))
+        );
+    }
+
+    #[test]
+    fn poll_response() {
+        let online = Response::DiskOnline(DiskSet::TF).to_bytes();
+        let mut bad = Response::Ack.to_bytes();
+        bad[8] ^= 0xff;
+        let mut stream = FakeStream::default();
+        stream.chunks.push_back(Ok(online[..6].to_vec()));
+        stream.chunks.push_back(Err(ErrorKind::WouldBlock.into()));
+        stream.chunks.push_back(Ok(online[6..].to_vec()));
+        stream.chunks.push_back(Ok(bad.to_vec()));
+        let mut serial = MioParser::from_stream(stream);
+
+        assert_eq!(serial.poll_response(), Err(nb::Error::WouldBlock));
+        assert_eq!(
+            nb::block!(serial.poll_response()),
+            Ok(Response::DiskOnline(DiskSet::TF))
+        );
+        assert!(matches!(
+            serial.poll_response(),
+            Err(nb::Error::Other(Error::BadChecksum { .. }))
+        ));
+        assert_eq!(serial.poll_response(), Err(nb::Error::WouldBlock));
+    }
+
+    #[test]
+    fn read_errors() {
+        let mut bad = Response::Ack.to_bytes();
+        bad[8] ^= 0xff;
+        let mut stream = FakeStream::default();
+        stream.chunks.push_back(Ok(bad.to_vec()));
+        stream.chunks.push_back(Err(ErrorKind::BrokenPipe.into()));
+        stream.chunks.push_back(Ok(Vec::new()));
+        let mut serial = MioParser::from_stream(stream);
+
+        assert!(matches!(
+            serial.try_recv_response(),
+            Some(Err(Error::BadChecksum { .. }))
+        ));
+        assert_eq!(serial.try_recv_response(), Some(Err(Error::IoRead)));
+        // end of input
+        assert_eq!(serial.try_recv_response(), Some(Err(Error::IoRead)));
+    }
+}
@@ -0,0 +1,209 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! In-memory stand-in for a serial connection to the device, for unit
+//! testing code without the hardware.
+
+use crate::{
+    Command, Error, ParseResult, Parser, RequestAck, Response, Result,
+};
+use std::collections::VecDeque;
+use std::vec::Vec;
+
+/// Fake serial connection which records every command sent to it and
+/// returns responses which have been queued up by the test.
+///
+/// ## Example
+/// ```
+/// use dfr0299::{mock::MockSerial, Command, Response};
+/// let mut serial = MockSerial::new();
+/// serial.send_command(Command::Reset).unwrap();
+/// serial.inject_response(Response::Ack);
+/// assert_eq!(serial.recv_response(), Ok(Response::Ack));
+/// serial.assert_sent(&[Command::Reset]);
+/// ```
+#[derive(Debug, Default)]
+pub struct MockSerial {
+    sent: Vec<u8>,
+    incoming: VecDeque<u8>,
+    parser: Parser,
+}
+
+impl MockSerial {
+    /// Create a mock with nothing sent or queued
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a command as sent without requesting an ACK
+    pub fn send_command(&mut self, cmd: Command) -> Result<()> {
+        self.send_command_with_ack(cmd, RequestAck::No)
+    }
+
+    /// Record a command as sent with the given ACK flag
+    pub fn send_command_with_ack(
+        &mut self,
+        cmd: Command,
+        request_ack: impl Into<RequestAck>,
+    ) -> Result<()> {
+        let mut buf = [0u8; 10];
+        cmd.serialise_with_ack(&mut buf, request_ack)?;
+        self.sent.extend_from_slice(&buf);
+        Ok(())
+    }
+
+    /// Parse the next queued response. Returns `Error::IoRead` if the
+    /// queued bytes run out before a complete response is parsed.
+    pub fn recv_response(&mut self) -> Result<Response> {
+        self.try_recv_response()?.ok_or(Error::IoRead)
+    }
+
+    /// Parse the next queued response, returning `None` if the queued
+    /// bytes run out before a complete response is parsed. Any bytes of
+    /// a partial response are kept by the parser, so injecting the rest
+    /// of the packet allows it to be received.
+    pub fn try_recv_response(&mut self) -> Result<Option<Response>> {
+        while let Some(byte) = self.incoming.pop_front() {
+            if let ParseResult::Complete(resp) =
+                self.parser.process_byte(byte)?
+            {
+                return Ok(Some(resp));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Queue up a response packet to be received
+    pub fn inject_response(&mut self, resp: Response) {
+        self.inject_raw(&resp.to_bytes());
+    }
+
+    /// Queue up raw bytes to be received, e.g. to simulate noise or a
+    /// corrupted packet
+    pub fn inject_raw(&mut self, bytes: &[u8]) {
+        self.incoming.extend(bytes);
+    }
+
+    /// All bytes sent so far
+    pub fn sent_bytes(&self) -> &[u8] {
+        &self.sent
+    }
+
+    /// Parse the commands sent so far. Command bytes which aren't known
+    /// to `Command::parse` are returned as `Command::Custom`.
+    ///
+    /// ## Panics
+    /// Panics if the sent bytes are not a sequence of valid packets,
+    /// e.g. if a command with an invalid parameter value was sent.
+    pub fn sent_packets(&self) -> Vec<Command> {
+        self.sent
+            .chunks(10)
+            .map(|chunk| {
+                let packet = chunk.try_into().expect("partial packet sent");
+                match Command::try_from_bytes(packet) {
+                    Ok((cmd, _)) => cmd,
+                    Err(Error::InvalidCommand(cmd)) => Command::Custom {
+                        cmd,
+                        param: u16::from_be_bytes([packet[5], packet[6]]),
+                    },
+                    Err(e) => panic!("invalid packet {packet:02x?}: {e}"),
+                }
+            })
+            .collect()
+    }
+
+    /// Assert that exactly these commands have been sent so far
+    ///
+    /// ## Panics
+    /// Panics if the sent commands differ from `expected`.
+    #[track_caller]
+    pub fn assert_sent(&self, expected: &[Command]) {
+        assert_eq!(self.sent_packets(), expected, "unexpected commands sent");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, DiskSet, ModuleErrorType};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn send_and_receive() {
+        let mut serial = MockSerial::new();
+        serial.send_command(Command::Reset).unwrap();
+        serial
+            .send_command_with_ack(Command::Track(2), RequestAck::Yes)
+            .unwrap();
+        serial
+            .send_command(Command::Custom {
+                cmd: 0x5a,
+                param: 0x1234,
+            })
+            .unwrap();
+        serial.assert_sent(&[
+            Command::Reset,
+            Command::Track(2),
+            Command::Custom {
+                cmd: 0x5a,
+                param: 0x1234,
+            },
+        ]);
+        assert_eq!(serial.sent_packets()[0], Command::Reset);
+        assert_eq!(serial.sent_bytes().len(), 30);
+
+        serial.inject_response(Response::Ack);
+        assert_eq!(serial.recv_response(), Ok(Response::Ack));
+        assert_eq!(serial.recv_response(), Err(Error::IoRead));
+    }
+
+    #[test]
+    fn injected_responses_parse() {
+        use Response::*;
+        let responses = [
+            Ack,
+            DiskOnline(DiskSet::UDISK | DiskSet::TF),
+            UDiskFinishPlayback(1),
+            TfFinishPlayback(0x1234),
+            FlashFinishPlayback(3),
+            ModuleError(ModuleErrorType::Busy),
+            DiskInserted(Disk::Tf),
+            DiskRemoved(Disk::UDiskAndFlash),
+        ];
+        let mut serial = MockSerial::new();
+        for resp in responses {
+            serial.inject_response(resp);
+        }
+        for resp in responses {
+            assert_eq!(serial.try_recv_response(), Ok(Some(resp)));
+        }
+        assert_eq!(serial.try_recv_response(), Ok(None));
+    }
+
+    #[test]
+    fn inject_raw_bytes() {
+        let packet = Response::DiskInserted(Disk::Tf).to_bytes();
+        let mut serial = MockSerial::new();
+
+        serial.inject_raw(&[0x00, 0x12]);
+        serial.inject_raw(&packet[..4]);
+        assert_eq!(serial.try_recv_response(), Ok(None));
+        serial.inject_raw(&packet[4..]);
+        assert_eq!(
+            serial.try_recv_response(),
+            Ok(Some(Response::DiskInserted(Disk::Tf)))
+        );
+
+        let mut corrupt = packet;
+        corrupt[6] ^= 0x01;
+        serial.inject_raw(&corrupt);
+        assert_eq!(
+            serial.recv_response(),
+            Err(Error::BadChecksum {
+                expected: [0xfe, 0xbe],
+                received: [0xfe, 0xbf],
+            })
+        );
+    }
+}
@@ -0,0 +1,332 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Packet-level checksum and framing validation.
+
+use crate::proto::*;
+use crate::{Command, PacketError, RequestAck, Response, Result};
+
+/// The fields of a packet, between the raw bytes and the typed
+/// `Command` and `Response` enums. This is useful for handling command
+/// or response codes which this crate doesn't know about.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Packet {
+    /// Version field, always `VERSION` in a valid packet
+    pub version: u8,
+    /// Command byte
+    pub cmd: u8,
+    /// Feedback byte. For commands this is whether an ACK was
+    /// requested
+    pub feedback: u8,
+    /// Parameter high byte
+    pub param_h: u8,
+    /// Parameter low byte
+    pub param_l: u8,
+}
+
+impl Packet {
+    /// Parse a complete packet, validating its framing and checksum as
+    /// with `validate_packet`
+    pub fn from_bytes(buf: &[u8; 10]) -> Result<Self> {
+        validate_packet(buf)?;
+        Ok(Self {
+            version: buf[IDX_VERSION],
+            cmd: buf[IDX_CMD],
+            feedback: buf[IDX_FEEDBACK],
+            param_h: buf[IDX_PARAM_H],
+            param_l: buf[IDX_PARAM_L],
+        })
+    }
+
+    /// Serialise the packet with its checksum. The feedback byte is
+    /// replaced by `request_ack`.
+    pub fn to_bytes(self, request_ack: impl Into<RequestAck>) -> [u8; 10] {
+        let feedback = request_ack.into() as u8;
+        let [checksum_h, checksum_l] = calculate_checksum(
+            self.version,
+            LEN,
+            self.cmd,
+            feedback,
+            self.param_h,
+            self.param_l,
+        )
+        .to_be_bytes();
+        [
+            START,
+            self.version,
+            LEN,
+            self.cmd,
+            feedback,
+            self.param_h,
+            self.param_l,
+            checksum_h,
+            checksum_l,
+            STOP,
+        ]
+    }
+
+    /// Interpret the packet as a command sent from the host, returning
+    /// the command and whether it requested an ACK
+    pub fn as_command(&self) -> Result<(Command, RequestAck)> {
+        let request_ack = RequestAck::try_from(self.feedback)?;
+        let cmd = Command::parse(self.cmd, self.param_h, self.param_l)?;
+        Ok((cmd, request_ack))
+    }
+
+    /// Interpret the packet as a response sent by the device
+    pub fn as_response(&self) -> Result<Response> {
+        Response::parse(self.cmd, self.param_h, self.param_l)
+    }
+}
+
+/// Fill `buf` with a packet for an arbitrary command byte and
+/// parameter, e.g. for experimenting with undocumented commands without
+/// going through `Command::Custom`
+///
+/// ## Example
+/// ```
+/// use dfr0299::{serialise_raw_packet, Command, RequestAck};
+/// let mut buf = [0u8; 10];
+/// serialise_raw_packet(0x03, 1, RequestAck::No, &mut buf);
+/// assert_eq!(buf, Command::Track(1).to_bytes());
+/// ```
+pub fn serialise_raw_packet(
+    cmd: u8,
+    param: u16,
+    request_ack: impl Into<RequestAck>,
+    buf: &mut [u8; 10],
+) {
+    let [param_h, param_l] = param.to_be_bytes();
+    let packet = Packet {
+        version: VERSION,
+        cmd,
+        feedback: 0,
+        param_h,
+        param_l,
+    };
+    *buf = packet.to_bytes(request_ack);
+}
+
+/// Calculate the checksum of a packet from its data bytes. This is the
+/// twos complement of the sum of the bytes between START and the
+/// checksum, and is sent big-endian in the packet.
+pub fn calculate_checksum(
+    version: u8,
+    len: u8,
+    cmd: u8,
+    feedback: u8,
+    param_h: u8,
+    param_l: u8,
+) -> i16 {
+    -[version, len, cmd, feedback, param_h, param_l]
+        .into_iter()
+        .map(i16::from)
+        .sum::<i16>()
+}
+
+/// Check the framing and checksum of a complete 10-byte packet,
+/// returning the `PacketError` for the first problem found. The fields
+/// are checked in the order start, version, length, stop, checksum.
+pub fn validate_packet(
+    buf: &[u8; 10],
+) -> core::result::Result<(), PacketError> {
+    if buf[IDX_START] != START {
+        return Err(PacketError::BadStart);
+    }
+    if buf[IDX_VERSION] != VERSION {
+        return Err(PacketError::BadVersion);
+    }
+    if buf[IDX_LEN] != LEN {
+        return Err(PacketError::BadLength);
+    }
+    if buf[IDX_STOP] != STOP {
+        return Err(PacketError::BadStop);
+    }
+
+    let expected = calculate_checksum(
+        buf[IDX_VERSION],
+        buf[IDX_LEN],
+        buf[IDX_CMD],
+        buf[IDX_FEEDBACK],
+        buf[IDX_PARAM_H],
+        buf[IDX_PARAM_L],
+    )
+    .to_be_bytes();
+    let received = [buf[IDX_CHECKSUM_H], buf[IDX_CHECKSUM_L]];
+    if expected != received {
+        return Err(PacketError::BadChecksum { expected, received });
+    }
+    Ok(())
+}
+
+/// Whether a complete 10-byte packet has valid framing and checksum, as
+/// with `validate_packet`
+pub fn is_valid_packet(buf: &[u8; 10]) -> bool {
+    validate_packet(buf).is_ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Error;
+    use pretty_assertions::assert_eq;
+
+    /// Sample packets used elsewhere in the test suite
+    const PACKETS: &[[u8; 10]] = &[
+        [0x7e, 0xff, 0x06, 0x03, 0x00, 0x00, 0x01, 0xfe, 0xf7, 0xef],
+        [0x7e, 0xff, 0x06, 0x03, 0x01, 0x00, 0x01, 0xfe, 0xf6, 0xef],
+        [0x7e, 0xff, 0x06, 0x09, 0x00, 0x00, 0x04, 0xfe, 0xee, 0xef],
+        [0x7e, 0xff, 0x06, 0x13, 0x00, 0x00, 0x01, 0xfe, 0xe7, 0xef],
+        [0x7e, 0xff, 0x06, 0x14, 0x00, 0x02, 0x05, 0xfe, 0xe0, 0xef],
+        [0x7e, 0xff, 0x06, 0x15, 0x00, 0x00, 0x00, 0xfe, 0xe6, 0xef],
+        [0x7e, 0xff, 0x06, 0x16, 0x00, 0x00, 0x00, 0xfe, 0xe5, 0xef],
+        [0x7e, 0xff, 0x06, 0x17, 0x00, 0x00, 0x02, 0xfe, 0xe2, 0xef],
+        [0x7e, 0xff, 0x06, 0x18, 0x00, 0x00, 0x00, 0xfe, 0xe3, 0xef],
+        [0x7e, 0xff, 0x06, 0x19, 0x00, 0x00, 0x00, 0xfe, 0xe2, 0xef],
+        [0x7e, 0xff, 0x06, 0x19, 0x00, 0x00, 0x01, 0xfe, 0xe1, 0xef],
+        [0x7e, 0xff, 0x06, 0x1a, 0x00, 0x00, 0x00, 0xfe, 0xe1, 0xef],
+        [0x7e, 0xff, 0x06, 0x1a, 0x00, 0x00, 0x01, 0xfe, 0xe0, 0xef],
+        [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef],
+        [0x7e, 0xff, 0x06, 0x3f, 0x00, 0x00, 0x02, 0xfe, 0xba, 0xef],
+    ];
+
+    #[test]
+    fn sample_packet_checksums() {
+        for packet in PACKETS {
+            let checksum = calculate_checksum(
+                packet[1], packet[2], packet[3], packet[4], packet[5],
+                packet[6],
+            );
+            assert_eq!(checksum.to_be_bytes(), [packet[7], packet[8]]);
+            assert_eq!(validate_packet(packet), Ok(()), "{packet:02x?}");
+        }
+    }
+
+    #[test]
+    fn packet_round_trips() {
+        for packet in PACKETS {
+            let parsed = Packet::from_bytes(packet).unwrap();
+            let ack = RequestAck::try_from(packet[4]).unwrap();
+            assert_eq!(parsed.to_bytes(ack), *packet);
+        }
+
+        let cmd = Command::SetFolder { folder: 4, file: 1 };
+        for ack in [RequestAck::No, RequestAck::Yes] {
+            let mut buf = [0u8; 10];
+            cmd.serialise_with_ack(&mut buf, ack).unwrap();
+            let packet = Packet::from_bytes(&buf).unwrap();
+            assert_eq!(
+                packet,
+                Packet {
+                    version: VERSION,
+                    cmd: 0x0f,
+                    feedback: ack as u8,
+                    param_h: 4,
+                    param_l: 1,
+                }
+            );
+            assert_eq!(packet.as_command(), Ok((cmd, ack)));
+            assert_eq!(packet.to_bytes(ack), buf);
+        }
+
+        let resp = Response::DiskInserted(crate::Disk::Tf);
+        let packet = Packet::from_bytes(&resp.to_bytes()).unwrap();
+        assert_eq!(packet.as_response(), Ok(resp));
+        assert_eq!(packet.to_bytes(RequestAck::No), resp.to_bytes());
+
+        // a custom packet which neither enum understands
+        let custom = Packet {
+            version: VERSION,
+            cmd: 0x60,
+            feedback: 0,
+            param_h: 0x12,
+            param_l: 0x34,
+        };
+        let bytes = custom.to_bytes(RequestAck::No);
+        assert_eq!(Packet::from_bytes(&bytes), Ok(custom));
+        assert_eq!(custom.as_command(), Err(Error::InvalidCommand(0x60)));
+        assert_eq!(custom.as_response(), Err(Error::InvalidCommand(0x60)));
+        assert_eq!(
+            Packet {
+                feedback: 0x02,
+                ..custom
+            }
+            .as_command(),
+            Err(Error::InvalidParameterValue(0x02))
+        );
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn serde_round_trip() {
+        crate::assert_serde_round_trip(
+            Packet::from_bytes(&PACKETS[0]).unwrap(),
+        );
+    }
+
+    #[test]
+    fn raw_packets() {
+        let mut buf = [0u8; 10];
+        let mut expected = [0u8; 10];
+        for ack in [RequestAck::No, RequestAck::Yes] {
+            serialise_raw_packet(0x03, 1, ack, &mut buf);
+            Command::Track(1)
+                .serialise_with_ack(&mut expected, ack)
+                .unwrap();
+            assert_eq!(buf, expected);
+        }
+
+        serialise_raw_packet(0x5a, 0x1234, false, &mut buf);
+        assert_eq!(
+            buf,
+            Command::Custom {
+                cmd: 0x5a,
+                param: 0x1234
+            }
+            .to_bytes()
+        );
+        assert_eq!(validate_packet(&buf), Ok(()));
+    }
+
+    #[test]
+    fn invalid_packets() {
+        let corrupt = |idx: usize, value: u8| {
+            let mut packet = PACKETS[0];
+            packet[idx] = value;
+            validate_packet(&packet)
+        };
+        assert_eq!(corrupt(0, 0x00), Err(PacketError::BadStart));
+        assert_eq!(corrupt(1, 0x00), Err(PacketError::BadVersion));
+        assert_eq!(corrupt(2, 0x07), Err(PacketError::BadLength));
+        assert_eq!(corrupt(9, 0x00), Err(PacketError::BadStop));
+        assert_eq!(
+            corrupt(6, 0x02),
+            Err(PacketError::BadChecksum {
+                expected: [0xfe, 0xf6],
+                received: [0xfe, 0xf7],
+            })
+        );
+        assert_eq!(
+            corrupt(8, 0xf6),
+            Err(PacketError::BadChecksum {
+                expected: [0xfe, 0xf7],
+                received: [0xfe, 0xf6],
+            })
+        );
+        // the first problem is reported
+        let mut packet = PACKETS[0];
+        packet[1] = 0x00;
+        packet[9] = 0x00;
+        assert_eq!(validate_packet(&packet), Err(PacketError::BadVersion));
+        assert!(!is_valid_packet(&packet));
+        assert_eq!(Packet::from_bytes(&packet), Err(Error::BadVersion));
+
+        for packet in PACKETS {
+            assert_eq!(validate_packet(packet), Ok(()));
+            assert!(is_valid_packet(packet));
+        }
+    }
+}
@@ -0,0 +1,593 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Range-validated command parameters.
+
+use crate::{Command, Error, Result};
+use core::fmt::{self, Display, Formatter};
+
+/// Volume level, validated to be within the range 0-30 given in the
+/// datasheet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "use_serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u16", into = "u16")
+)]
+pub struct Volume(u8);
+
+impl Volume {
+    /// Maximum volume supported by the device
+    pub const MAX: u8 = 30;
+
+    /// Create a `Volume`, returning `Error::VolumeOutOfRange` if it is
+    /// greater than 30
+    pub fn new(volume: u8) -> Result<Self> {
+        if volume > Self::MAX {
+            return Err(Error::VolumeOutOfRange);
+        }
+        Ok(Self(volume))
+    }
+
+    /// The volume level
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Volume {
+    type Error = Error;
+
+    fn try_from(volume: u8) -> Result<Self> {
+        Self::new(volume)
+    }
+}
+
+impl TryFrom<u16> for Volume {
+    type Error = Error;
+
+    fn try_from(volume: u16) -> Result<Self> {
+        u8::try_from(volume)
+            .map_err(|_| Error::VolumeOutOfRange)
+            .and_then(Self::new)
+    }
+}
+
+impl From<Volume> for u16 {
+    fn from(volume: Volume) -> Self {
+        volume.0.into()
+    }
+}
+
+impl Display for Volume {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}/{}", self.0, Self::MAX)
+    }
+}
+
+/// Track number, validated to be within the range 1-2999 given in the
+/// datasheet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "use_serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "u16", into = "u16")
+)]
+pub struct TrackNumber(u16);
+
+impl TrackNumber {
+    /// Lowest track number supported by the device
+    pub const MIN: u16 = 1;
+    /// Highest track number supported by the device
+    pub const MAX: u16 = 2999;
+
+    /// Create a `TrackNumber`, returning `Error::TrackOutOfRange` if it
+    /// is zero or greater than 2999
+    pub fn new(track: u16) -> Result<Self> {
+        if !(Self::MIN..=Self::MAX).contains(&track) {
+            return Err(Error::TrackOutOfRange(track));
+        }
+        Ok(Self(track))
+    }
+
+    /// The track number
+    pub fn get(self) -> u16 {
+        self.0
+    }
+}
+
+impl TryFrom<u16> for TrackNumber {
+    type Error = Error;
+
+    fn try_from(track: u16) -> Result<Self> {
+        Self::new(track)
+    }
+}
+
+impl From<TrackNumber> for u16 {
+    fn from(track: TrackNumber) -> Self {
+        track.0
+    }
+}
+
+impl Display for TrackNumber {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.0)
+    }
+}
+
+/// Parameters of `Command::SetVolumeAdjust`, with the gain validated
+/// to be within the range 0-31 given in the datasheet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "use_serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "(bool, u8)", into = "(bool, u8)")
+)]
+pub struct VolumeAdjust {
+    enable: bool,
+    gain: u8,
+}
+
+impl VolumeAdjust {
+    /// Maximum gain supported by the device
+    pub const MAX_GAIN: u8 = 31;
+
+    /// Create a `VolumeAdjust`, returning `Error::GainOutOfRange` if the
+    /// gain is greater than 31
+    pub fn new(enable: bool, gain: u8) -> Result<Self> {
+        if gain > Self::MAX_GAIN {
+            return Err(Error::GainOutOfRange(gain));
+        }
+        Ok(Self { enable, gain })
+    }
+
+    /// Whether the gain is enabled
+    pub fn enable(self) -> bool {
+        self.enable
+    }
+
+    /// The gain
+    pub fn gain(self) -> u8 {
+        self.gain
+    }
+}
+
+impl TryFrom<(bool, u8)> for VolumeAdjust {
+    type Error = Error;
+
+    fn try_from((enable, gain): (bool, u8)) -> Result<Self> {
+        Self::new(enable, gain)
+    }
+}
+
+impl From<VolumeAdjust> for (bool, u8) {
+    fn from(adjust: VolumeAdjust) -> Self {
+        (adjust.enable, adjust.gain)
+    }
+}
+
+impl From<VolumeAdjust> for Command {
+    fn from(VolumeAdjust { enable, gain }: VolumeAdjust) -> Self {
+        Self::SetVolumeAdjust { enable, gain }
+    }
+}
+
+/// Parameters of `Command::SetFolderTrack`, with the folder validated
+/// to be within the range 0-15 and the track within the range 0-999 so
+/// that both can be packed into the 16-bit parameter
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(
+    feature = "use_serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "(u16, u16)", into = "(u16, u16)")
+)]
+pub struct FolderTrack {
+    folder: u16,
+    track: u16,
+}
+
+impl FolderTrack {
+    /// Highest folder number which fits into the parameter
+    pub const MAX_FOLDER: u16 = 15;
+    /// Highest track number which can be addressed in a folder
+    pub const MAX_TRACK: u16 = 999;
+
+    /// Create a `FolderTrack`, returning `Error::FolderOutOfRange` if
+    /// the folder is greater than 15 or `Error::TrackOutOfRange` if the
+    /// track is greater than 999
+    pub fn new(folder: u16, track: u16) -> Result<Self> {
+        if folder > Self::MAX_FOLDER {
+            return Err(Error::FolderOutOfRange(folder));
+        }
+        if track > Self::MAX_TRACK {
+            return Err(Error::TrackOutOfRange(track));
+        }
+        Ok(Self { folder, track })
+    }
+
+    /// The folder number
+    pub fn folder(self) -> u16 {
+        self.folder
+    }
+
+    /// The track number within the folder
+    pub fn track(self) -> u16 {
+        self.track
+    }
+}
+
+impl TryFrom<(u16, u16)> for FolderTrack {
+    type Error = Error;
+
+    fn try_from((folder, track): (u16, u16)) -> Result<Self> {
+        Self::new(folder, track)
+    }
+}
+
+impl From<FolderTrack> for (u16, u16) {
+    fn from(folder_track: FolderTrack) -> Self {
+        (folder_track.folder, folder_track.track)
+    }
+}
+
+impl From<FolderTrack> for Command {
+    fn from(folder_track: FolderTrack) -> Self {
+        Self::SetFolderTrack(folder_track)
+    }
+}
+
+#[cfg(feature = "use_arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for FolderTrack {
+    fn arbitrary(
+        u: &mut arbitrary::Unstructured<'a>,
+    ) -> arbitrary::Result<Self> {
+        Ok(Self {
+            folder: u.int_in_range(0..=Self::MAX_FOLDER)?,
+            track: u.int_in_range(0..=Self::MAX_TRACK)?,
+        })
+    }
+}
+
+impl Command {
+    /// Construct a `Command::SetVolume`, returning
+    /// `Error::VolumeOutOfRange` if the volume is greater than 30
+    pub fn set_volume(volume: u8) -> Result<Self> {
+        Volume::new(volume).map(|v| Self::SetVolume(v.into()))
+    }
+
+    /// Construct a `Command::Track`, returning `Error::TrackOutOfRange`
+    /// if the track is zero or greater than 2999
+    pub fn play_track(track: u16) -> Result<Self> {
+        TrackNumber::new(track).map(|t| Self::Track(t.into()))
+    }
+
+    /// Construct a `Command::SetFolder`, returning
+    /// `Error::FolderOutOfRange` if the folder is not in the range 1-99
+    /// or `Error::FileOutOfRange` if the file is zero. On the card the
+    /// folder must be named with two digits and the file with four
+    /// zero-padded digits, e.g. `07/0042.mp3`.
+    pub fn try_set_folder(folder: u8, file: u8) -> Result<Self> {
+        if !(1..=99).contains(&folder) {
+            return Err(Error::FolderOutOfRange(folder.into()));
+        }
+        if file == 0 {
+            return Err(Error::FileOutOfRange(file));
+        }
+        Ok(Self::SetFolder { folder, file })
+    }
+
+    /// Construct a `Command::SetFolderTrack`, returning
+    /// `Error::FolderOutOfRange` if the folder is greater than 15 or
+    /// `Error::TrackOutOfRange` if the track is greater than 999
+    pub fn try_set_folder_track(folder: u16, track: u16) -> Result<Self> {
+        FolderTrack::new(folder, track).map(Self::from)
+    }
+
+    /// Construct a `Command::SetVolumeAdjust`, returning
+    /// `Error::GainOutOfRange` if the gain is greater than 31
+    pub fn try_set_volume_adjust(enable: bool, gain: u8) -> Result<Self> {
+        VolumeAdjust::new(enable, gain).map(Self::from)
+    }
+
+    /// Construct a `Command::SetVolumeAdjust` which enables the given
+    /// gain, returning `Error::GainOutOfRange` if it is greater than 31
+    pub fn enable_volume_adjust(gain: u8) -> Result<Self> {
+        Self::try_set_volume_adjust(true, gain)
+    }
+
+    /// Construct a `Command::SetVolumeAdjust` which disables the gain
+    pub fn disable_volume_adjust() -> Self {
+        Self::SetVolumeAdjust {
+            enable: false,
+            gain: 0,
+        }
+    }
+}
+
+/// Validated construction of commands, for callers who don't want to
+/// keep track of the range of each parameter. Parameterless commands
+/// are included for completeness.
+///
+/// ## Example
+/// ```
+/// use dfr0299::{Command, CommandBuilder, Error};
+/// assert_eq!(CommandBuilder::volume(20), Ok(Command::SetVolume(20)));
+/// assert_eq!(CommandBuilder::track(0), Err(Error::TrackOutOfRange(0)));
+/// ```
+#[derive(Copy, Clone, Debug)]
+pub struct CommandBuilder;
+
+impl CommandBuilder {
+    /// Maximum value of the gain parameter of
+    /// `Command::SetVolumeAdjust`
+    pub const MAX_GAIN: u8 = VolumeAdjust::MAX_GAIN;
+
+    /// `Command::Track`, as with `Command::play_track`
+    pub fn track(track: u16) -> Result<Command> {
+        Command::play_track(track)
+    }
+
+    /// `Command::SetVolume`, as with `Command::set_volume`
+    pub fn volume(volume: u8) -> Result<Command> {
+        Command::set_volume(volume)
+    }
+
+    /// `Command::SetFolder`, as with `Command::try_set_folder`
+    pub fn folder(folder: u8, file: u8) -> Result<Command> {
+        Command::try_set_folder(folder, file)
+    }
+
+    /// `Command::SetVolumeAdjust`, as with
+    /// `Command::try_set_volume_adjust`
+    pub fn volume_adjust(enable: bool, gain: u8) -> Result<Command> {
+        Command::try_set_volume_adjust(enable, gain)
+    }
+
+    /// `Command::Reset`
+    pub fn reset() -> Command {
+        Command::Reset
+    }
+
+    /// `Command::Next`
+    pub fn next() -> Command {
+        Command::Next
+    }
+
+    /// `Command::Previous`
+    pub fn previous() -> Command {
+        Command::Previous
+    }
+
+    /// `Command::Playback`
+    pub fn playback() -> Command {
+        Command::Playback
+    }
+
+    /// `Command::Pause`
+    pub fn pause() -> Command {
+        Command::Pause
+    }
+
+    /// `Command::Stop`
+    pub fn stop() -> Command {
+        Command::Stop
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn volume_range() {
+        for v in [0, 15, 30] {
+            assert_eq!(Volume::new(v).unwrap().get(), v);
+            assert_eq!(Volume::try_from(u16::from(v)).unwrap().get(), v);
+            assert_eq!(
+                Command::set_volume(v),
+                Ok(Command::SetVolume(v.into()))
+            );
+        }
+        for v in [31, 255] {
+            assert_eq!(Volume::new(v), Err(Error::VolumeOutOfRange));
+            assert_eq!(Volume::try_from(v), Err(Error::VolumeOutOfRange));
+            assert_eq!(Command::set_volume(v), Err(Error::VolumeOutOfRange));
+        }
+        assert_eq!(Volume::try_from(0x100_u16), Err(Error::VolumeOutOfRange));
+    }
+
+    #[test]
+    fn volume_display() {
+        assert_eq!(Volume::new(25).unwrap().to_string(), "25/30");
+    }
+
+    #[test]
+    fn track_range() {
+        for t in [1, 2, 1500, 2999] {
+            assert_eq!(TrackNumber::new(t).unwrap().get(), t);
+            assert_eq!(u16::from(TrackNumber::try_from(t).unwrap()), t);
+            assert_eq!(Command::play_track(t), Ok(Command::Track(t)));
+        }
+        for t in [0, 3000, 60000] {
+            assert_eq!(TrackNumber::new(t), Err(Error::TrackOutOfRange(t)));
+            assert_eq!(
+                TrackNumber::try_from(t),
+                Err(Error::TrackOutOfRange(t))
+            );
+            assert_eq!(Command::play_track(t), Err(Error::TrackOutOfRange(t)));
+        }
+        assert_eq!(TrackNumber::new(42).unwrap().to_string(), "42");
+    }
+
+    #[test]
+    fn folder_range() {
+        for (folder, file) in [(1, 1), (99, 1), (1, 255), (50, 100)] {
+            assert_eq!(
+                Command::try_set_folder(folder, file),
+                Ok(Command::SetFolder { folder, file })
+            );
+        }
+        for folder in [0, 100, 255] {
+            assert_eq!(
+                Command::try_set_folder(folder, 1),
+                Err(Error::FolderOutOfRange(folder.into()))
+            );
+        }
+        assert_eq!(
+            Command::try_set_folder(1, 0),
+            Err(Error::FileOutOfRange(0))
+        );
+        // the folder is checked first
+        assert_eq!(
+            Command::try_set_folder(0, 0),
+            Err(Error::FolderOutOfRange(0))
+        );
+    }
+
+    #[test]
+    fn folder_track_range() {
+        for (folder, track) in [(0, 0), (15, 0), (0, 999), (3, 500)] {
+            let folder_track = FolderTrack::new(folder, track).unwrap();
+            assert_eq!(
+                (folder_track.folder(), folder_track.track()),
+                (folder, track)
+            );
+            assert_eq!(
+                FolderTrack::try_from((folder, track)),
+                Ok(folder_track)
+            );
+            assert_eq!(
+                Command::try_set_folder_track(folder, track),
+                Ok(Command::SetFolderTrack(folder_track))
+            );
+        }
+        for folder in [16, 255, 256, 0xffff] {
+            assert_eq!(
+                Command::try_set_folder_track(folder, 1),
+                Err(Error::FolderOutOfRange(folder))
+            );
+        }
+        for track in [1000, 4095, 0xffff] {
+            assert_eq!(
+                Command::try_set_folder_track(1, track),
+                Err(Error::TrackOutOfRange(track))
+            );
+        }
+    }
+
+    #[test]
+    fn volume_adjust_range() {
+        for gain in [0, 31] {
+            let adjust = VolumeAdjust::new(true, gain).unwrap();
+            assert_eq!((adjust.enable(), adjust.gain()), (true, gain));
+            assert_eq!(
+                VolumeAdjust::try_from((false, gain)).unwrap().gain(),
+                gain
+            );
+            assert_eq!(
+                Command::try_set_volume_adjust(false, gain),
+                Ok(Command::SetVolumeAdjust {
+                    enable: false,
+                    gain
+                })
+            );
+            assert_eq!(
+                Command::enable_volume_adjust(gain),
+                Ok(Command::SetVolumeAdjust { enable: true, gain })
+            );
+        }
+        for gain in [32, 255] {
+            assert_eq!(
+                VolumeAdjust::new(true, gain),
+                Err(Error::GainOutOfRange(gain))
+            );
+            assert_eq!(
+                VolumeAdjust::try_from((false, gain)),
+                Err(Error::GainOutOfRange(gain))
+            );
+            assert_eq!(
+                Command::try_set_volume_adjust(true, gain),
+                Err(Error::GainOutOfRange(gain))
+            );
+            assert_eq!(
+                Command::enable_volume_adjust(gain),
+                Err(Error::GainOutOfRange(gain))
+            );
+        }
+        assert_eq!(
+            Command::disable_volume_adjust(),
+            Command::SetVolumeAdjust {
+                enable: false,
+                gain: 0
+            }
+        );
+    }
+
+    #[test]
+    fn command_builder() {
+        assert_eq!(CommandBuilder::track(1), Ok(Command::Track(1)));
+        assert_eq!(CommandBuilder::track(2999), Ok(Command::Track(2999)));
+        assert_eq!(CommandBuilder::track(0), Err(Error::TrackOutOfRange(0)));
+        assert_eq!(
+            CommandBuilder::track(3000),
+            Err(Error::TrackOutOfRange(3000))
+        );
+
+        assert_eq!(CommandBuilder::volume(0), Ok(Command::SetVolume(0)));
+        assert_eq!(CommandBuilder::volume(30), Ok(Command::SetVolume(30)));
+        assert_eq!(CommandBuilder::volume(31), Err(Error::VolumeOutOfRange));
+
+        assert_eq!(
+            CommandBuilder::folder(99, 255),
+            Ok(Command::SetFolder {
+                folder: 99,
+                file: 255
+            })
+        );
+        assert_eq!(
+            CommandBuilder::folder(100, 1),
+            Err(Error::FolderOutOfRange(100))
+        );
+        assert_eq!(CommandBuilder::folder(1, 0), Err(Error::FileOutOfRange(0)));
+
+        for gain in [0, 31] {
+            assert_eq!(
+                CommandBuilder::volume_adjust(true, gain),
+                Ok(Command::SetVolumeAdjust { enable: true, gain })
+            );
+        }
+        for gain in [32, 255] {
+            assert_eq!(
+                CommandBuilder::volume_adjust(false, gain),
+                Err(Error::GainOutOfRange(gain))
+            );
+        }
+
+        assert_eq!(CommandBuilder::reset(), Command::Reset);
+        assert_eq!(CommandBuilder::next(), Command::Next);
+        assert_eq!(CommandBuilder::previous(), Command::Previous);
+        assert_eq!(CommandBuilder::playback(), Command::Playback);
+        assert_eq!(CommandBuilder::pause(), Command::Pause);
+        assert_eq!(CommandBuilder::stop(), Command::Stop);
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn serde_round_trip() {
+        crate::assert_serde_round_trip(Volume::new(30).unwrap());
+        crate::assert_serde_round_trip(TrackNumber::new(2999).unwrap());
+        crate::assert_serde_round_trip(VolumeAdjust::new(true, 31).unwrap());
+        crate::assert_serde_round_trip(FolderTrack::new(15, 999).unwrap());
+        assert!(serde_json::from_str::<FolderTrack>("[16, 1]").is_err());
+        assert!(serde_json::from_str::<VolumeAdjust>("[true, 32]").is_err());
+        assert!(serde_json::from_str::<TrackNumber>("0").is_err());
+        assert!(serde_json::from_str::<Volume>("31").is_err());
+    }
+}
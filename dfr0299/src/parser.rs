@@ -2,12 +2,16 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{Error, Response, Result, START, STOP, VERSION};
+use crate::proto::{LEN, START, STOP, VERSION};
+use crate::{Error, Response, Result};
+use core::fmt::{self, Display, Formatter};
 
-const LEN: u8 = 6;
+#[cfg(any(feature = "log", feature = "tracing"))]
+const LOG_TARGET: &str = "dfr0299::parser";
 
-#[derive(Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 enum ParserState {
+    #[default]
     Idle,
     Start,
     Version,
@@ -20,21 +24,68 @@ enum ParserState {
     ChecksumL,
 }
 
-impl Default for ParserState {
-    fn default() -> Self {
-        Self::Idle
-    }
-}
-
 /// After processing a byte the parser will return either `Incomplete`
-/// to indicate that it requires more data or `Complete` to indicate
-/// that a full message has been successfully processed
+/// to indicate that it requires more data, `Complete` to indicate
+/// that a full message has been successfully processed, or `Ignored`
+/// if the byte was discarded due to a framing error
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ParseResult {
     /// Waiting for more bytes
     Incomplete,
     /// Complete message has been processed
     Complete(Response),
+    /// The byte didn't match what was expected partway through a
+    /// packet, so it and the partial packet have been discarded. Bytes
+    /// received while idle which aren't `START` are dropped silently
+    /// and reported as `Incomplete`.
+    ///
+    /// Parsers created with `Parser::new_permissive` also return this
+    /// with the command byte of a valid packet whose command is not
+    /// recognised.
+    Ignored(u8),
+}
+
+/// A `ParseResult` along with the feedback byte of a completed packet,
+/// as returned by `Parser::process_byte_full`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseResultFull {
+    /// Result of processing the byte
+    pub result: ParseResult,
+    /// Feedback byte of the packet if `result` is
+    /// `ParseResult::Complete`. For `Response::Ack` this is expected to
+    /// be the command byte being acknowledged.
+    pub feedback: Option<u8>,
+}
+
+/// Counters kept by a `Parser`, e.g. for monitoring the health of the
+/// connection. The counters wrap around on overflow.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParserStats {
+    /// Number of bytes passed to `Parser::process_byte`
+    pub bytes_received: u32,
+    /// Number of complete messages parsed
+    pub messages_parsed: u32,
+    /// Number of complete packets discarded due to a bad checksum
+    pub checksum_errors: u32,
+    /// Number of times a partial packet was discarded due to an
+    /// unexpected byte
+    pub framing_errors: u32,
+}
+
+impl Display for ParserStats {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(
+            fmt,
+            "{} bytes received, {} messages parsed, {} checksum errors, \
+            {} framing errors",
+            self.bytes_received,
+            self.messages_parsed,
+            self.checksum_errors,
+            self.framing_errors
+        )
+    }
 }
 
 /// Parser for the DFR0299 response messages. After initialising, calls
@@ -51,6 +102,9 @@ pub enum ParseResult {
 ///        uart.read_exact(&mut buf)?;
 ///        match parser.process_byte(buf[0]) {
 ///            Ok(ParseResult::Incomplete) => {}
+///            Ok(ParseResult::Ignored(byte)) => {
+///                println!("Ignored unexpected byte {byte:#04x}");
+///            }
 ///            Ok(ParseResult::Complete(msg)) => {
 ///                println!("Message received: {msg:?}");
 ///            }
@@ -70,6 +124,17 @@ pub struct Parser {
     param_l: u8,
     checksum_h: u8,
     checksum_l: u8,
+    timeout_ms: Option<u32>,
+    since_last_byte_ms: u32,
+    permissive: bool,
+    stats: ParserStats,
+    /// Responses parsed from bytes written via `embedded_io::Write`
+    #[cfg(feature = "embedded-io")]
+    received: heapless::Vec<Response, 4>,
+    /// Error from bytes written via `embedded_io::Write`, returned by
+    /// the next call to `write` or `flush`
+    #[cfg(feature = "embedded-io")]
+    write_error: Option<Error>,
 }
 
 impl Parser {
@@ -78,32 +143,259 @@ impl Parser {
         Self::default()
     }
 
+    /// Initialise a new `Parser` which discards a partially received
+    /// packet if no bytes arrive for more than `threshold` milliseconds.
+    /// The passage of time is reported to the parser by calling
+    /// `Parser::check_timeout`.
+    pub fn with_timeout_ms(threshold: u32) -> Self {
+        Self {
+            timeout_ms: Some(threshold),
+            ..Self::default()
+        }
+    }
+
+    /// Initialise a new `Parser` which discards packets with
+    /// unrecognised command bytes, returning `ParseResult::Ignored`
+    /// with the command byte rather than `Error::InvalidCommand`. This
+    /// is useful for clone modules which send non-standard responses.
+    pub fn new_permissive() -> Self {
+        Self {
+            permissive: true,
+            ..Self::default()
+        }
+    }
+
+    /// Report that `elapsed_ms` milliseconds have passed since the
+    /// previous call to this function, or since the last byte was
+    /// received. If the parser is partway through a packet and the
+    /// total time since the last byte exceeds the threshold given to
+    /// `Parser::with_timeout_ms` then the partial packet is discarded
+    /// and this returns `true`.
+    ///
+    /// Parsers created with `Parser::new` have no threshold and never
+    /// time out.
+    pub fn check_timeout(&mut self, elapsed_ms: u32) -> bool {
+        self.since_last_byte_ms =
+            self.since_last_byte_ms.saturating_add(elapsed_ms);
+        if self.timed_out() {
+            self.reset();
+            return true;
+        }
+        false
+    }
+
+    fn timed_out(&self) -> bool {
+        !matches!(self.state, ParserState::Idle)
+            && self
+                .timeout_ms
+                .is_some_and(|threshold| self.since_last_byte_ms > threshold)
+    }
+
+    /// Discard any partially received packet and return to the idle
+    /// state, e.g. after the UART reports an overrun or framing error
+    pub const fn reset(&mut self) {
+        self.state = ParserState::Idle;
+        self.cmd = 0;
+        self.feedback = 0;
+        self.param_h = 0;
+        self.param_l = 0;
+        self.checksum_h = 0;
+        self.checksum_l = 0;
+        self.since_last_byte_ms = 0;
+    }
+
+    /// Reset the parser as with `Parser::reset`, and also clear the
+    /// accumulated statistics
+    pub const fn reset_with_stats(&mut self) {
+        self.reset();
+        self.reset_stats();
+    }
+
+    /// Counters accumulated since the parser was created or the stats
+    /// were last reset
+    pub fn stats(&self) -> &ParserStats {
+        &self.stats
+    }
+
+    /// Clear the accumulated statistics without affecting any partially
+    /// received packet
+    pub const fn reset_stats(&mut self) {
+        self.stats = ParserStats {
+            bytes_received: 0,
+            messages_parsed: 0,
+            checksum_errors: 0,
+            framing_errors: 0,
+        };
+    }
+
     /// Process a single byte and advance the internal state machine
     /// accordingly. Returns a `ParseResult` indicating the parsing
     /// status or an `Error::BadChecksum` if a complete message has been
     /// received but the checksum is incorrect.
+    ///
+    /// If a byte doesn't match what is expected partway through a
+    /// packet then the partial packet is discarded and the byte is
+    /// treated as though the parser were idle, so that a `START` byte
+    /// immediately following some noise still begins a new packet. Any
+    /// other unexpected byte is returned as `ParseResult::Ignored`.
+    ///
+    /// If the parser has a timeout and the threshold has been exceeded
+    /// since the previous byte then the partial packet is discarded
+    /// before this byte is processed.
     pub fn process_byte(&mut self, byte: u8) -> Result<ParseResult> {
-        use ParserState::*;
-        self.state = match self.state {
-            Idle => {
-                if byte == START {
-                    Start
-                } else {
-                    Idle
+        self.process_byte_full(byte).map(|full| full.result)
+    }
+
+    /// Process a single byte as with `Parser::process_byte`, also
+    /// returning the feedback byte of a completed packet
+    pub fn process_byte_full(&mut self, byte: u8) -> Result<ParseResultFull> {
+        if self.timed_out() {
+            self.reset();
+        }
+        self.since_last_byte_ms = 0;
+
+        #[cfg(any(feature = "log", feature = "tracing"))]
+        let prev = self.state;
+        let result = self.advance(byte);
+        #[cfg(feature = "log")]
+        self.log_result(prev, byte, &result);
+        #[cfg(feature = "tracing")]
+        self.trace_result(prev, byte, &result);
+
+        let stats = &mut self.stats;
+        stats.bytes_received = stats.bytes_received.wrapping_add(1);
+        match result {
+            Ok(ParseResult::Complete(_)) => {
+                stats.messages_parsed = stats.messages_parsed.wrapping_add(1);
+            }
+            Err(Error::BadChecksum { .. }) => {
+                stats.checksum_errors = stats.checksum_errors.wrapping_add(1);
+            }
+            _ => {}
+        }
+        result.map(|result| ParseResultFull {
+            feedback: matches!(result, ParseResult::Complete(_))
+                .then_some(self.feedback),
+            result,
+        })
+    }
+
+    /// Process each byte in turn, calling `callback` with the result of
+    /// every byte which was processed successfully. Errors are not
+    /// passed to the callback, but are still counted in the stats.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            target = "dfr0299::parser",
+            skip_all
+        )
+    )]
+    pub fn extend_with_callback<I, F>(&mut self, bytes: I, mut callback: F)
+    where
+        I: IntoIterator<Item = u8>,
+        F: FnMut(ParseResult),
+    {
+        for byte in bytes {
+            if let Ok(result) = self.process_byte(byte) {
+                callback(result);
+            }
+        }
+    }
+
+    /// Process each byte in turn and collect the complete responses. If
+    /// an error occurs it is returned immediately and any remaining
+    /// bytes are not consumed from the iterator. Returns
+    /// `Error::QueueFull` if there are more than 8 responses.
+    #[cfg(feature = "heapless")]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            target = "dfr0299::parser",
+            skip_all
+        )
+    )]
+    pub fn extend_collecting(
+        &mut self,
+        bytes: impl IntoIterator<Item = u8>,
+    ) -> Result<heapless::Vec<Response, 8>> {
+        let mut responses = heapless::Vec::new();
+        for byte in bytes {
+            if let ParseResult::Complete(resp) = self.process_byte(byte)? {
+                responses.push(resp).map_err(|_| Error::QueueFull)?;
+            }
+        }
+        Ok(responses)
+    }
+
+    /// Process bytes from `buf` until a complete response or an error
+    /// is found, e.g. when handling a DMA transfer interrupt. Returns
+    /// the response or error along with the number of bytes consumed,
+    /// so that the remainder can be passed to another call as
+    /// `&buf[consumed..]`. If the buffer does not complete a response
+    /// then this returns `(None, buf.len())`, and any partial packet is
+    /// kept for the next call.
+    ///
+    /// Noise between packets is skipped in a single scan for `START`
+    /// rather than being stepped through the state machine.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            target = "dfr0299::parser",
+            skip_all,
+            fields(len = buf.len())
+        )
+    )]
+    pub fn feed_dma_buffer(
+        &mut self,
+        buf: &[u8],
+    ) -> (Option<Result<Response>>, usize) {
+        let mut idx = 0;
+        while idx < buf.len() {
+            if matches!(self.state, ParserState::Idle) {
+                let skip = buf[idx..]
+                    .iter()
+                    .position(|&byte| byte == START)
+                    .unwrap_or(buf.len() - idx);
+                self.stats.bytes_received =
+                    self.stats.bytes_received.wrapping_add(skip as u32);
+                idx += skip;
+                if idx == buf.len() {
+                    break;
                 }
             }
+
+            let byte = buf[idx];
+            idx += 1;
+            match self.process_byte(byte) {
+                Ok(ParseResult::Complete(resp)) => {
+                    return (Some(Ok(resp)), idx)
+                }
+                Err(err) => return (Some(Err(err)), idx),
+                Ok(_) => {}
+            }
+        }
+        (None, buf.len())
+    }
+
+    fn advance(&mut self, byte: u8) -> Result<ParseResult> {
+        use ParserState::*;
+        self.state = match self.state {
+            Idle => Self::resync(byte),
             Start => {
                 if byte == VERSION {
                     Version
                 } else {
-                    Idle
+                    return Ok(self.framing_error(byte));
                 }
             }
             Version => {
                 if byte == LEN {
                     Len
                 } else {
-                    Idle
+                    return Ok(self.framing_error(byte));
                 }
             }
             Len => {
@@ -135,37 +427,188 @@ impl Parser {
                     // do the thing
                     self.state = Idle;
 
-                    let checksum = self.calculate_checksum();
-                    if checksum.to_be_bytes()
-                        != [self.checksum_h, self.checksum_l]
-                    {
-                        return Err(Error::BadChecksum);
+                    let expected = self.calculate_checksum().to_be_bytes();
+                    let received = [self.checksum_h, self.checksum_l];
+                    if expected != received {
+                        return Err(Error::BadChecksum { expected, received });
                     }
 
                     // checksum valid -> parse message
-                    let response =
-                        Response::parse(self.cmd, self.param_h, self.param_l)?;
-                    return Ok(ParseResult::Complete(response));
+                    return match Response::parse_with_feedback(
+                        self.cmd,
+                        self.feedback,
+                        self.param_h,
+                        self.param_l,
+                    ) {
+                        Ok((response, _)) => {
+                            Ok(ParseResult::Complete(response))
+                        }
+                        Err(Error::InvalidCommand(cmd)) if self.permissive => {
+                            Ok(ParseResult::Ignored(cmd))
+                        }
+                        Err(err) => Err(err),
+                    };
                 }
-                Idle
+                return Ok(self.framing_error(byte));
             }
         };
 
         Ok(ParseResult::Incomplete)
     }
 
+    /// State to move to on receiving `byte` while looking for the start
+    /// of a packet
+    fn resync(byte: u8) -> ParserState {
+        if byte == START {
+            ParserState::Start
+        } else {
+            ParserState::Idle
+        }
+    }
+
+    /// Discard the partial packet after receiving an unexpected byte
+    fn framing_error(&mut self, byte: u8) -> ParseResult {
+        #[cfg(feature = "log")]
+        log::warn!(
+            target: LOG_TARGET,
+            "framing error: unexpected byte {byte:#04x} in state {:?}",
+            self.state
+        );
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            target: LOG_TARGET,
+            byte,
+            state = ?self.state,
+            "framing error"
+        );
+        self.stats.framing_errors = self.stats.framing_errors.wrapping_add(1);
+        self.state = Self::resync(byte);
+        match self.state {
+            ParserState::Idle => ParseResult::Ignored(byte),
+            _ => ParseResult::Incomplete,
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    fn trace_result(
+        &self,
+        prev: ParserState,
+        byte: u8,
+        result: &Result<ParseResult>,
+    ) {
+        tracing::trace!(
+            target: LOG_TARGET,
+            from = ?prev,
+            to = ?self.state,
+            byte,
+            "state transition"
+        );
+        match result {
+            Ok(ParseResult::Complete(response)) => {
+                tracing::debug!(target: LOG_TARGET, ?response, "parsed");
+            }
+            Err(Error::BadChecksum { expected, received }) => {
+                tracing::warn!(
+                    target: LOG_TARGET,
+                    ?expected,
+                    ?received,
+                    "bad checksum"
+                );
+            }
+            _ => {}
+        }
+    }
+
+    #[cfg(feature = "log")]
+    fn log_result(
+        &self,
+        prev: ParserState,
+        byte: u8,
+        result: &Result<ParseResult>,
+    ) {
+        log::trace!(
+            target: LOG_TARGET,
+            "{prev:?} -> {:?} on {byte:#04x}",
+            self.state
+        );
+        match result {
+            Ok(ParseResult::Complete(response)) => {
+                log::debug!(target: LOG_TARGET, "parsed {response:?}");
+            }
+            Err(err @ Error::BadChecksum { .. }) => {
+                log::warn!(target: LOG_TARGET, "{err}");
+            }
+            _ => {}
+        }
+    }
+
     fn calculate_checksum(&self) -> i16 {
-        -[
-            0xff,
+        crate::calculate_checksum(
+            VERSION,
             LEN,
             self.cmd,
             self.feedback,
             self.param_h,
             self.param_l,
-        ]
-        .into_iter()
-        .map(i16::from)
-        .sum::<i16>()
+        )
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl Parser {
+    /// Take the responses which have been parsed from bytes written via
+    /// `embedded_io::Write`, making room for more
+    pub fn drain_responses(&mut self) -> heapless::Vec<Response, 4> {
+        core::mem::take(&mut self.received)
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+impl embedded_io::ErrorType for Parser {
+    type Error = Error;
+}
+
+/// Bytes written to the parser are processed as with
+/// `Parser::extend_with_callback`, and complete responses are kept until
+/// they are taken with `Parser::drain_responses`. Up to 4 responses are
+/// kept: once there are 4 waiting, `write` stops consuming bytes, and
+/// returns `Error::QueueFull` if none of the bytes could be consumed.
+///
+/// If a byte completes a packet which fails to parse, e.g. with an
+/// `Error::BadChecksum`, then `write` stops after that byte and reports
+/// the bytes up to and including it as consumed. The error is returned
+/// by the next call to `write` or `flush`, without consuming anything,
+/// so `write_all` fails with the error of the first bad packet.
+#[cfg(feature = "embedded-io")]
+impl embedded_io::Write for Parser {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if let Some(err) = self.write_error.take() {
+            return Err(err);
+        }
+        if self.received.is_full() && !buf.is_empty() {
+            return Err(Error::QueueFull);
+        }
+        for (idx, &byte) in buf.iter().enumerate() {
+            match self.process_byte(byte) {
+                Ok(ParseResult::Complete(resp)) => {
+                    // there is always room, as this stops once it's full
+                    let _ = self.received.push(resp);
+                    if self.received.is_full() {
+                        return Ok(idx + 1);
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => {
+                    self.write_error = Some(err);
+                    return Ok(idx + 1);
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.write_error.take().map_or(Ok(()), Err)
     }
 }
 
@@ -200,8 +643,700 @@ mod test {
                     assert_eq!(msg, expected);
                     ok = true;
                 }
+                ParseResult::Ignored(byte) => panic!("ignored {byte:#04x}"),
             }
         }
         assert!(ok);
     }
+
+    #[test]
+    fn bad_checksum_values() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0x12, 0x34, 0xef];
+        let mut parser = Parser::new();
+        for byte in &msg[..9] {
+            assert_eq!(parser.process_byte(*byte), Ok(ParseResult::Incomplete));
+        }
+        let err = parser.process_byte(msg[9]).unwrap_err();
+        assert_eq!(
+            err,
+            Error::BadChecksum {
+                expected: [0xfe, 0xbf],
+                received: [0x12, 0x34],
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "BadChecksum: expected febf, received 1234"
+        );
+    }
+
+    #[test]
+    fn resync_after_framing_error() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::response::Disk::UDisk);
+
+        // a START in each position where a specific byte is expected
+        for noise in [&[0x7e][..], &[0x7e, 0xff], &msg[..9]] {
+            let mut parser = Parser::new();
+            let mut responses = Vec::new();
+            for &byte in noise.iter().chain(&msg) {
+                if let ParseResult::Complete(msg) =
+                    parser.process_byte(byte).unwrap()
+                {
+                    responses.push(msg);
+                }
+            }
+            assert_eq!(responses, [expected], "noise: {noise:02x?}");
+        }
+    }
+
+    #[test]
+    fn ignored_bytes() {
+        use ParseResult::*;
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let mut parser = Parser::new();
+
+        // noise while idle is expected
+        assert_eq!(parser.process_byte(0x00), Ok(Incomplete));
+        assert_eq!(parser.process_byte(0xef), Ok(Incomplete));
+
+        for (bytes, expected) in [
+            (&[0x7e, 0x00][..], Ignored(0x00)),
+            (&[0x7e, 0xff, 0x07], Ignored(0x07)),
+            (
+                &[0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0x12],
+                Ignored(0x12),
+            ),
+            // a START restarts the packet rather than being ignored
+            (&[0x7e, 0x7e], Incomplete),
+        ] {
+            let (last, head) = bytes.split_last().unwrap();
+            for byte in head {
+                assert_eq!(parser.process_byte(*byte), Ok(Incomplete));
+            }
+            assert_eq!(parser.process_byte(*last), Ok(expected));
+            parser.reset();
+        }
+
+        // the parser recovers after ignoring a byte
+        parser.process_byte(0x7e).unwrap();
+        assert_eq!(parser.process_byte(0x01), Ok(Ignored(0x01)));
+        let mut results = Vec::new();
+        for byte in msg {
+            results.push(parser.process_byte(byte).unwrap());
+        }
+        assert_eq!(
+            results.last(),
+            Some(&Complete(Response::DiskRemoved(
+                crate::response::Disk::UDisk
+            )))
+        );
+    }
+
+    #[test]
+    fn ack_feedback_byte() {
+        let [checksum_h, checksum_l] =
+            crate::calculate_checksum(VERSION, LEN, 0x41, 0x03, 0x00, 0x00)
+                .to_be_bytes();
+        let ack = [
+            0x7e, 0xff, 0x06, 0x41, 0x03, 0x00, 0x00, checksum_h, checksum_l,
+            0xef,
+        ];
+        let mut parser = Parser::new();
+        for byte in &ack[..9] {
+            assert_eq!(
+                parser.process_byte_full(*byte),
+                Ok(ParseResultFull {
+                    result: ParseResult::Incomplete,
+                    feedback: None,
+                })
+            );
+        }
+        assert_eq!(
+            parser.process_byte_full(ack[9]),
+            Ok(ParseResultFull {
+                result: ParseResult::Complete(Response::Ack),
+                feedback: Some(0x03),
+            })
+        );
+    }
+
+    #[test]
+    fn permissive_mode() {
+        let unknown =
+            [0x7e, 0xff, 0x06, 0x30, 0x00, 0x00, 0x00, 0xfe, 0xcb, 0xef];
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::Disk::UDisk);
+
+        let mut parser = Parser::new();
+        let results: Vec<_> =
+            unknown.iter().map(|b| parser.process_byte(*b)).collect();
+        assert_eq!(results.last(), Some(&Err(Error::InvalidCommand(0x30))));
+
+        let mut parser = Parser::new_permissive();
+        let results: Vec<_> = unknown
+            .iter()
+            .chain(&msg)
+            .map(|b| parser.process_byte(*b).unwrap())
+            .collect();
+        assert_eq!(results[9], ParseResult::Ignored(0x30));
+        assert_eq!(results[19], ParseResult::Complete(expected));
+        assert!(results
+            .iter()
+            .enumerate()
+            .all(|(i, r)| i == 9 || i == 19 || *r == ParseResult::Incomplete));
+
+        // invalid parameters are still reported
+        let bad_param =
+            [0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x07, 0xfe, 0xba, 0xef];
+        let results: Vec<_> =
+            bad_param.iter().map(|b| parser.process_byte(*b)).collect();
+        assert_eq!(
+            results.last(),
+            Some(&Err(Error::InvalidParameterValue(0x07)))
+        );
+    }
+
+    #[test]
+    fn stats_counters() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let mut bad_checksum = msg;
+        bad_checksum[8] = 0x00;
+        let mut bad_stop = msg;
+        bad_stop[9] = 0x00;
+
+        let mut parser = Parser::new();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&msg);
+        bytes.extend_from_slice(&[0x00, 0x12]); // idle noise
+        bytes.extend_from_slice(&bad_checksum);
+        bytes.extend_from_slice(&[0x7e, 0xff, 0x00]); // bad length
+        bytes.extend_from_slice(&bad_stop);
+        bytes.extend_from_slice(&msg);
+        for byte in &bytes {
+            let _ = parser.process_byte(*byte);
+        }
+        assert_eq!(
+            *parser.stats(),
+            ParserStats {
+                bytes_received: bytes.len() as u32,
+                messages_parsed: 2,
+                checksum_errors: 1,
+                framing_errors: 2,
+            }
+        );
+        assert_eq!(
+            parser.stats().to_string(),
+            "45 bytes received, 2 messages parsed, 1 checksum errors, \
+            2 framing errors"
+        );
+
+        // reset keeps the stats, reset_stats keeps the partial packet
+        parser.process_byte(0x7e).unwrap();
+        parser.reset();
+        assert_eq!(parser.stats().bytes_received, 46);
+        parser.process_byte(0x7e).unwrap();
+        parser.reset_stats();
+        assert_eq!(*parser.stats(), ParserStats::default());
+        assert!(matches!(parser.state, ParserState::Start));
+
+        parser.process_byte(0x00).unwrap();
+        parser.reset_with_stats();
+        assert_eq!(*parser.stats(), ParserStats::default());
+    }
+
+    #[test]
+    fn extend_with_callback() {
+        let msgs = [
+            0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef, //
+            0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x02, 0xfe, 0xbf, 0xef,
+        ];
+        let mut parser = Parser::new();
+        let mut results = Vec::new();
+        parser.extend_with_callback(msgs, |result| results.push(result));
+
+        assert_eq!(results.len(), 20);
+        let complete: Vec<_> = results
+            .into_iter()
+            .filter_map(|result| match result {
+                ParseResult::Complete(resp) => Some(resp),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            complete,
+            [
+                Response::DiskRemoved(crate::Disk::UDisk),
+                Response::DiskInserted(crate::Disk::Tf)
+            ]
+        );
+    }
+
+    #[test]
+    fn feed_dma_buffer() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::Disk::UDisk);
+        let mut bad_checksum = msg;
+        bad_checksum[8] = 0x00;
+
+        let mut buf = vec![0x00, 0x12];
+        buf.extend_from_slice(&msg);
+        buf.extend_from_slice(&bad_checksum);
+        buf.extend_from_slice(&[0xef, 0x7e, 0xff, 0x00]); // bad length
+        buf.extend_from_slice(&msg);
+        buf.extend_from_slice(&msg[..4]);
+
+        let mut parser = Parser::new();
+        let mut rest = &buf[..];
+        let mut results = Vec::new();
+        loop {
+            let (result, consumed) = parser.feed_dma_buffer(rest);
+            rest = &rest[consumed..];
+            match result {
+                Some(result) => results.push(result),
+                None => break,
+            }
+        }
+        assert!(rest.is_empty());
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0], Ok(expected));
+        assert!(matches!(results[1], Err(Error::BadChecksum { .. })));
+        assert_eq!(results[2], Ok(expected));
+
+        // the trailing partial packet is completed by the next buffer
+        assert_eq!(parser.feed_dma_buffer(&msg[4..]), (Some(Ok(expected)), 6));
+        assert_eq!(parser.feed_dma_buffer(&[]), (None, 0));
+        assert_eq!(
+            *parser.stats(),
+            ParserStats {
+                bytes_received: buf.len() as u32 + 6,
+                messages_parsed: 3,
+                checksum_errors: 1,
+                framing_errors: 1,
+            }
+        );
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn extend_collecting() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::Disk::UDisk);
+        let mut parser = Parser::new();
+
+        let responses = parser.extend_collecting(msg.repeat(2)).unwrap();
+        assert_eq!(responses, [expected, expected]);
+
+        assert_eq!(
+            parser.extend_collecting(msg.repeat(9)),
+            Err(Error::QueueFull)
+        );
+
+        let mut bad_checksum = msg;
+        bad_checksum[8] = 0x00;
+        parser.reset();
+        let mut bytes = bad_checksum.into_iter().chain(msg);
+        assert!(matches!(
+            parser.extend_collecting(&mut bytes),
+            Err(Error::BadChecksum { .. })
+        ));
+        // the remaining packet is left in the iterator
+        assert_eq!(parser.extend_collecting(bytes).unwrap(), [expected]);
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded_io_write() {
+        use embedded_io::Write;
+        let online = Response::DiskOnline(crate::DiskSet::TF).to_bytes();
+        let ack = Response::Ack.to_bytes();
+        let mut parser = Parser::new();
+
+        parser.write_all(&[online, ack].concat()).unwrap();
+        assert_eq!(
+            parser.drain_responses(),
+            [Response::DiskOnline(crate::DiskSet::TF), Response::Ack]
+        );
+        assert!(parser.drain_responses().is_empty());
+
+        // bytes after the 4th response are left unconsumed
+        let bytes = ack.repeat(5);
+        assert_eq!(parser.write(&bytes), Ok(40));
+        assert_eq!(parser.write(&bytes[40..]), Err(Error::QueueFull));
+        assert_eq!(parser.write(&[]), Ok(0));
+        assert_eq!(parser.drain_responses(), [Response::Ack; 4]);
+        assert_eq!(parser.write(&bytes[40..]), Ok(10));
+        assert_eq!(parser.drain_responses(), [Response::Ack]);
+        assert_eq!(parser.stats().messages_parsed, 7);
+    }
+
+    #[cfg(feature = "embedded-io")]
+    #[test]
+    fn embedded_io_write_errors() {
+        use embedded_io::Write;
+        let ack = Response::Ack.to_bytes();
+        let mut corrupt = ack;
+        corrupt[8] ^= 0xff;
+        let bytes = [ack, corrupt, ack].concat();
+        let mut parser = Parser::new();
+
+        // bytes up to the end of the bad packet are consumed, and the
+        // error is returned by the next call
+        assert_eq!(parser.write(&bytes), Ok(20));
+        assert!(matches!(
+            parser.write(&bytes[20..]),
+            Err(Error::BadChecksum { .. })
+        ));
+        assert_eq!(parser.write(&bytes[20..]), Ok(10));
+        assert_eq!(parser.flush(), Ok(()));
+        assert_eq!(parser.drain_responses(), [Response::Ack; 2]);
+
+        // `write_all` stops at the first bad packet
+        assert!(matches!(
+            parser.write_all(&bytes),
+            Err(Error::BadChecksum { .. })
+        ));
+        assert_eq!(parser.drain_responses(), [Response::Ack]);
+
+        // an error from the last bytes written is returned by `flush`
+        assert_eq!(parser.write(&corrupt), Ok(10));
+        assert!(matches!(parser.flush(), Err(Error::BadChecksum { .. })));
+        assert_eq!(parser.flush(), Ok(()));
+        assert_eq!(parser.stats().checksum_errors, 3);
+    }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn stats_serde_round_trip() {
+        crate::assert_serde_round_trip(ParserStats {
+            bytes_received: 100_000,
+            messages_parsed: 10,
+            checksum_errors: 1,
+            framing_errors: 0,
+        });
+    }
+
+    #[test]
+    fn reset_discards_partial_packet() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::response::Disk::UDisk);
+
+        for reset in [Parser::reset, Parser::reset_with_stats] {
+            let mut parser = Parser::new();
+            for byte in &msg[..6] {
+                parser.process_byte(*byte).unwrap();
+            }
+            reset(&mut parser);
+            assert!(matches!(parser.state, ParserState::Idle));
+
+            let mut responses = Vec::new();
+            for byte in msg {
+                if let ParseResult::Complete(msg) =
+                    parser.process_byte(byte).unwrap()
+                {
+                    responses.push(msg);
+                }
+            }
+            assert_eq!(responses, [expected]);
+        }
+    }
+
+    #[test]
+    fn timeout_discards_partial_packet() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::response::Disk::UDisk);
+
+        let mut parser = Parser::with_timeout_ms(100);
+        assert!(!parser.check_timeout(1000));
+        for byte in &msg[..6] {
+            parser.process_byte(*byte).unwrap();
+        }
+        assert!(!parser.check_timeout(60));
+        assert!(!parser.check_timeout(40));
+        assert!(parser.check_timeout(1));
+        assert!(matches!(parser.state, ParserState::Idle));
+
+        let mut responses = Vec::new();
+        for byte in msg {
+            assert!(!parser.check_timeout(5));
+            if let ParseResult::Complete(msg) =
+                parser.process_byte(byte).unwrap()
+            {
+                responses.push(msg);
+            }
+        }
+        assert_eq!(responses, [expected]);
+    }
+
+    #[test]
+    fn timeout_checked_on_next_byte() {
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let expected = Response::DiskRemoved(crate::response::Disk::UDisk);
+
+        // a gap which exceeded the threshold without a call to
+        // check_timeout is noticed when the next byte arrives
+        let mut parser = Parser::with_timeout_ms(100);
+        for byte in &msg[..4] {
+            parser.process_byte(*byte).unwrap();
+        }
+        parser.since_last_byte_ms = 101;
+        let mut responses = Vec::new();
+        for byte in msg {
+            if let ParseResult::Complete(msg) =
+                parser.process_byte(byte).unwrap()
+            {
+                responses.push(msg);
+            }
+        }
+        assert_eq!(responses, [expected]);
+
+        // no threshold set
+        let mut parser = Parser::new();
+        parser.process_byte(msg[0]).unwrap();
+        assert!(!parser.check_timeout(u32::MAX));
+        assert!(matches!(parser.state, ParserState::Start));
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn log_records() {
+        use std::sync::Mutex;
+
+        struct Capture(Mutex<Vec<(log::Level, String, String)>>);
+
+        impl log::Log for Capture {
+            fn enabled(&self, _: &log::Metadata) -> bool {
+                true
+            }
+
+            fn log(&self, record: &log::Record) {
+                self.0.lock().unwrap().push((
+                    record.level(),
+                    record.target().to_string(),
+                    record.args().to_string(),
+                ));
+            }
+
+            fn flush(&self) {}
+        }
+
+        static LOGGER: Capture = Capture(Mutex::new(Vec::new()));
+        log::set_logger(&LOGGER).unwrap();
+        log::set_max_level(log::LevelFilter::Trace);
+
+        let mut buf = [0u8; 10];
+        crate::Command::Next.serialise(&mut buf).unwrap();
+
+        let msg = [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        let mut parser = Parser::new();
+        for byte in [0x7e, 0x00].iter().chain(&msg) {
+            parser.process_byte(*byte).unwrap();
+        }
+        let mut bad_checksum = msg;
+        bad_checksum[8] = 0x00;
+        for byte in bad_checksum {
+            let _ = parser.process_byte(byte);
+        }
+
+        let records = LOGGER.0.lock().unwrap();
+        for (level, target, text) in [
+            (
+                log::Level::Debug,
+                "dfr0299::serialise",
+                "Next: [7e, ff, 06, 01, 00, 00, 00, fe, fa, ef]",
+            ),
+            (
+                log::Level::Warn,
+                "dfr0299::parser",
+                "framing error: unexpected byte 0x00 in state Start",
+            ),
+            (
+                log::Level::Trace,
+                "dfr0299::parser",
+                "Idle -> Start on 0x7e",
+            ),
+            (
+                log::Level::Debug,
+                "dfr0299::parser",
+                "parsed DiskRemoved(UDisk)",
+            ),
+            (
+                log::Level::Warn,
+                "dfr0299::parser",
+                "BadChecksum: expected febf, received fe00",
+            ),
+        ] {
+            assert!(
+                records.iter().any(|(l, tg, tx)| {
+                    *l == level && tg == target && tx == text
+                }),
+                "{text}"
+            );
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn tracing_events() {
+        use std::fmt::Write;
+        use std::sync::{Arc, Mutex};
+        use tracing::field::{Field, Visit};
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Event, Level, Metadata};
+
+        /// Formats the fields of an event as `name=value` pairs
+        struct Fields(String);
+
+        impl Visit for Fields {
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                let _ = write!(self.0, " {}={value:?}", field.name());
+            }
+        }
+
+        #[derive(Clone, Default)]
+        struct Capture {
+            events: Arc<Mutex<Vec<(Level, String, String)>>>,
+            spans: Arc<Mutex<Vec<String>>>,
+        }
+
+        impl tracing::Subscriber for Capture {
+            fn enabled(&self, _: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, span: &Attributes) -> Id {
+                let mut spans = self.spans.lock().unwrap();
+                let mut fields = Fields(span.metadata().name().to_string());
+                span.record(&mut fields);
+                spans.push(fields.0);
+                Id::from_u64(spans.len() as u64)
+            }
+
+            fn record(&self, _: &Id, _: &Record) {}
+
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+            fn event(&self, event: &Event) {
+                let mut fields = Fields(String::new());
+                event.record(&mut fields);
+                self.events.lock().unwrap().push((
+                    *event.metadata().level(),
+                    event.metadata().target().to_string(),
+                    fields.0,
+                ));
+            }
+
+            fn enter(&self, _: &Id) {}
+
+            fn exit(&self, _: &Id) {}
+        }
+
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            let mut buf = [0u8; 10];
+            crate::Command::Next.serialise(&mut buf).unwrap();
+
+            let msg =
+                [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+            let mut parser = Parser::new();
+            let mut bad_checksum = msg;
+            bad_checksum[8] = 0x00;
+            let bytes: Vec<u8> = [0x7e, 0x00]
+                .iter()
+                .chain(&msg)
+                .chain(&bad_checksum)
+                .copied()
+                .collect();
+            let (resp, len) = parser.feed_dma_buffer(&bytes);
+            assert_eq!(
+                resp,
+                Some(Ok(Response::DiskRemoved(crate::Disk::UDisk)))
+            );
+            parser.extend_with_callback(bytes[len..].iter().copied(), |_| {});
+        });
+
+        let events = capture.events.lock().unwrap();
+        for (level, target, text) in [
+            (
+                Level::DEBUG,
+                "dfr0299::serialise",
+                " message=serialised bytes=[126, 255, 6, 1, 0, 0, 0, 254, \
+                 250, 239]",
+            ),
+            (
+                Level::WARN,
+                "dfr0299::parser",
+                " message=framing error byte=0 state=Start",
+            ),
+            (
+                Level::TRACE,
+                "dfr0299::parser",
+                " message=state transition from=Idle to=Start byte=126",
+            ),
+            (
+                Level::DEBUG,
+                "dfr0299::parser",
+                " message=parsed response=DiskRemoved(UDisk)",
+            ),
+            (
+                Level::WARN,
+                "dfr0299::parser",
+                " message=bad checksum expected=[254, 191] received=[254, 0]",
+            ),
+        ] {
+            assert!(
+                events.iter().any(|(l, tg, tx)| {
+                    *l == level && tg == target && tx == text
+                }),
+                "{text} not in {events:#?}"
+            );
+        }
+
+        let spans = capture.spans.lock().unwrap();
+        assert_eq!(
+            *spans,
+            [
+                "serialise_with_ack request_ack=No cmd=Next",
+                "feed_dma_buffer len=22",
+                "extend_with_callback",
+            ]
+        );
+    }
+
+    #[cfg(feature = "use_arbitrary")]
+    #[test]
+    fn arbitrary_commands_parse_without_panic() {
+        use crate::{Command, RequestAck};
+        use arbitrary::{Arbitrary, Unstructured};
+
+        // cheap deterministic pseudo-random input data
+        let mut state = 0x2545_f491_u32;
+        let data: Vec<u8> = (0..4096)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+
+        let mut u = Unstructured::new(&data);
+        while !u.is_empty() {
+            let cmd = Command::arbitrary(&mut u).unwrap();
+            let ack = RequestAck::arbitrary(&mut u).unwrap();
+            let mut buf = [0u8; 10];
+            cmd.serialise_with_ack(&mut buf, ack).unwrap();
+
+            let mut parser = Parser::new();
+            for byte in buf {
+                match parser.process_byte(byte) {
+                    Ok(_) => {}
+                    Err(Error::BadChecksum { .. }) => {
+                        panic!("{cmd:?} {buf:02x?}")
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,294 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! High-level player which tracks the state of the device.
+
+use crate::{
+    Command, Disk, DiskSet, EqMode, Error, ModuleErrorType, PlaybackSource,
+    RequestAck, Response, Result,
+};
+use embedded_io::Write;
+
+/// Playback state of the device, as far as the `Player` knows
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[allow(missing_docs)]
+pub enum PlayStatus {
+    Stopped,
+    Playing,
+    Paused,
+}
+
+/// Events reported by `Player::handle_response`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PlayerEvent {
+    /// The specified track finished playing
+    PlaybackComplete(u16),
+    /// The specified disks are online and ready for playback
+    DiskOnline(DiskSet),
+    /// The specified disk has been inserted
+    DiskInserted(Disk),
+    /// The specified disk has been removed
+    DiskRemoved(Disk),
+    /// The device acknowledged a volume change
+    VolumeChanged(u8),
+    /// The device reported an error
+    ModuleError(ModuleErrorType),
+}
+
+/// Wrapper around a writer connected to the device, which keeps track
+/// of the playback state and rejects commands which don't make sense
+/// in the current state (e.g. resuming when nothing is paused).
+///
+/// Every command is sent with `RequestAck::Yes`, so that the device's
+/// ACKs can be used to confirm state changes. Responses from the
+/// device should be passed to `Player::handle_response`.
+///
+/// ## Example
+/// ```no_run
+/// # fn a<W: embedded_io::Write>(uart: W) -> dfr0299::Result<()> {
+/// use dfr0299::{Player, PlaybackSource};
+/// let mut player = Player::new(uart);
+/// player.reset()?;
+/// // wait for Response::DiskOnline, then
+/// player.set_source(PlaybackSource::Tf)?;
+/// player.set_volume(20)?;
+/// player.play_track(1)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Player<W> {
+    writer: W,
+    status: PlayStatus,
+    volume: Option<u8>,
+    source: Option<PlaybackSource>,
+    pending_volume: Option<u8>,
+}
+
+impl<W: Write> Player<W> {
+    /// Wrap a writer. The state of the device is unknown until it has
+    /// been reset or configured, so the player starts off `Stopped`
+    /// with no known volume or source.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            status: PlayStatus::Stopped,
+            volume: None,
+            source: None,
+            pending_volume: None,
+        }
+    }
+
+    /// Current playback status
+    pub fn status(&self) -> PlayStatus {
+        self.status
+    }
+
+    /// Most recently set volume, if any
+    pub fn volume(&self) -> Option<u8> {
+        self.volume
+    }
+
+    /// Most recently selected playback source, if any
+    pub fn source(&self) -> Option<PlaybackSource> {
+        self.source
+    }
+
+    /// Release the underlying writer
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Reset the device, forgetting all known state
+    pub fn reset(&mut self) -> Result<()> {
+        self.send(Command::Reset)?;
+        self.status = PlayStatus::Stopped;
+        self.volume = None;
+        self.source = None;
+        self.pending_volume = None;
+        Ok(())
+    }
+
+    /// Start playing the specified track
+    pub fn play_track(&mut self, track: u16) -> Result<()> {
+        self.send(Command::Track(track))?;
+        self.status = PlayStatus::Playing;
+        Ok(())
+    }
+
+    /// Pause the current track. Returns `Error::InvalidState` if
+    /// nothing is playing.
+    pub fn pause(&mut self) -> Result<()> {
+        if self.status != PlayStatus::Playing {
+            return Err(Error::InvalidState);
+        }
+        self.send(Command::Pause)?;
+        self.status = PlayStatus::Paused;
+        Ok(())
+    }
+
+    /// Resume the paused track. Returns `Error::InvalidState` if
+    /// playback is not paused.
+    pub fn resume(&mut self) -> Result<()> {
+        if self.status != PlayStatus::Paused {
+            return Err(Error::InvalidState);
+        }
+        self.send(Command::Playback)?;
+        self.status = PlayStatus::Playing;
+        Ok(())
+    }
+
+    /// Stop playback
+    pub fn stop(&mut self) -> Result<()> {
+        self.send(Command::Stop)?;
+        self.status = PlayStatus::Stopped;
+        Ok(())
+    }
+
+    /// Set the volume. Returns `Error::VolumeOutOfRange` if the volume
+    /// is greater than 30.
+    pub fn set_volume(&mut self, volume: u8) -> Result<()> {
+        self.send(Command::set_volume(volume)?)?;
+        self.volume = Some(volume);
+        self.pending_volume = Some(volume);
+        Ok(())
+    }
+
+    /// Set the EQ preset
+    pub fn set_eq(&mut self, eq: EqMode) -> Result<()> {
+        self.send(Command::SetEq(eq))
+    }
+
+    /// Select the playback source
+    pub fn set_source(&mut self, source: PlaybackSource) -> Result<()> {
+        self.send(Command::SetPlaybackSource(source))?;
+        self.source = Some(source);
+        Ok(())
+    }
+
+    /// Update the tracked state from a response received from the
+    /// device, returning an event if the response is of interest
+    pub fn handle_response(&mut self, resp: Response) -> Option<PlayerEvent> {
+        use Response::*;
+        match resp {
+            Ack => self.pending_volume.take().map(PlayerEvent::VolumeChanged),
+            UDiskFinishPlayback(track)
+            | TfFinishPlayback(track)
+            | FlashFinishPlayback(track) => {
+                self.status = PlayStatus::Stopped;
+                Some(PlayerEvent::PlaybackComplete(track))
+            }
+            DiskOnline(disk) => Some(PlayerEvent::DiskOnline(disk)),
+            DiskInserted(disk) => Some(PlayerEvent::DiskInserted(disk)),
+            DiskRemoved(disk) => Some(PlayerEvent::DiskRemoved(disk)),
+            ModuleError(err) => Some(PlayerEvent::ModuleError(err)),
+        }
+    }
+
+    fn send(&mut self, cmd: Command) -> Result<()> {
+        self.pending_volume = None;
+        crate::io::send_command_with_ack(
+            &mut self.writer,
+            &cmd,
+            RequestAck::Yes,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn sent(player: Player<Vec<u8>>) -> Vec<Command> {
+        player
+            .into_inner()
+            .chunks(10)
+            .map(|chunk| {
+                let (cmd, ack) =
+                    Command::try_from_bytes(chunk.try_into().unwrap()).unwrap();
+                assert_eq!(ack, RequestAck::Yes);
+                cmd
+            })
+            .collect()
+    }
+
+    #[test]
+    fn startup_sequence() {
+        let mut player = Player::new(Vec::new());
+        player.reset().unwrap();
+        player.set_source(PlaybackSource::Tf).unwrap();
+        player.set_volume(20).unwrap();
+        player.set_eq(EqMode::Jazz).unwrap();
+        player.play_track(3).unwrap();
+
+        assert_eq!(player.status(), PlayStatus::Playing);
+        assert_eq!(player.volume(), Some(20));
+        assert_eq!(player.source(), Some(PlaybackSource::Tf));
+        assert_eq!(
+            sent(player),
+            [
+                Command::Reset,
+                Command::SetPlaybackSource(PlaybackSource::Tf),
+                Command::SetVolume(20),
+                Command::SetEq(EqMode::Jazz),
+                Command::Track(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_transitions() {
+        let mut player = Player::new(Vec::new());
+        assert_eq!(player.resume(), Err(Error::InvalidState));
+        assert_eq!(player.pause(), Err(Error::InvalidState));
+        assert_eq!(player.set_volume(31), Err(Error::VolumeOutOfRange));
+
+        player.play_track(1).unwrap();
+        assert_eq!(player.resume(), Err(Error::InvalidState));
+        player.pause().unwrap();
+        assert_eq!(player.status(), PlayStatus::Paused);
+        player.resume().unwrap();
+        player.stop().unwrap();
+        assert_eq!(player.status(), PlayStatus::Stopped);
+
+        // rejected commands are not sent
+        assert_eq!(
+            sent(player),
+            [
+                Command::Track(1),
+                Command::Pause,
+                Command::Playback,
+                Command::Stop
+            ]
+        );
+    }
+
+    #[test]
+    fn handle_responses() {
+        let mut player = Player::new(Vec::new());
+        player.set_volume(10).unwrap();
+        assert_eq!(
+            player.handle_response(Response::Ack),
+            Some(PlayerEvent::VolumeChanged(10))
+        );
+        assert_eq!(player.handle_response(Response::Ack), None);
+
+        player.play_track(7).unwrap();
+        assert_eq!(
+            player.handle_response(Response::TfFinishPlayback(7)),
+            Some(PlayerEvent::PlaybackComplete(7))
+        );
+        assert_eq!(player.status(), PlayStatus::Stopped);
+
+        assert_eq!(
+            player.handle_response(Response::DiskOnline(DiskSet::TF)),
+            Some(PlayerEvent::DiskOnline(DiskSet::TF))
+        );
+    }
+}
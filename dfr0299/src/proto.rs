@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Constants describing the layout of a packet on the wire. `START`,
+//! `STOP` and `VERSION` are also re-exported from the crate root.
+
+/// Packet start byte
+pub const START: u8 = 0x7e;
+/// Packet end byte
+pub const STOP: u8 = 0xef;
+/// Packet version field. This just seems to be hardcoded to `0xff` and
+/// not actually used for anything
+pub const VERSION: u8 = 0xff;
+/// Value of the length field: the number of bytes from VERSION to the
+/// end of the parameter, not including the checksum
+pub const LEN: u8 = 0x06;
+/// Total length of a packet in bytes
+pub const PACKET_LEN: usize = 10;
+
+/// Index of the start byte
+pub const IDX_START: usize = 0;
+/// Index of the version byte
+pub const IDX_VERSION: usize = 1;
+/// Index of the length byte
+pub const IDX_LEN: usize = 2;
+/// Index of the command byte
+pub const IDX_CMD: usize = 3;
+/// Index of the feedback byte
+pub const IDX_FEEDBACK: usize = 4;
+/// Index of the parameter high byte
+pub const IDX_PARAM_H: usize = 5;
+/// Index of the parameter low byte
+pub const IDX_PARAM_L: usize = 6;
+/// Index of the checksum high byte
+pub const IDX_CHECKSUM_H: usize = 7;
+/// Index of the checksum low byte
+pub const IDX_CHECKSUM_L: usize = 8;
+/// Index of the stop byte
+pub const IDX_STOP: usize = 9;
+
+/// Whether `buf` is the right length and has the expected START,
+/// VERSION, LEN and STOP bytes. The checksum is not checked; use
+/// `validate_packet` for full validation with a specific error.
+pub fn validate_frame_structure(buf: &[u8]) -> bool {
+    buf.len() == PACKET_LEN
+        && buf[IDX_START] == START
+        && buf[IDX_VERSION] == VERSION
+        && buf[IDX_LEN] == LEN
+        && buf[IDX_STOP] == STOP
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame_structure() {
+        let packet =
+            [0x7e, 0xff, 0x06, 0x03, 0x00, 0x00, 0x01, 0xfe, 0xf7, 0xef];
+        assert!(validate_frame_structure(&packet));
+
+        // the checksum is not checked
+        let mut bad_checksum = packet;
+        bad_checksum[IDX_CHECKSUM_L] = 0x00;
+        assert!(validate_frame_structure(&bad_checksum));
+
+        for idx in [IDX_START, IDX_VERSION, IDX_LEN, IDX_STOP] {
+            let mut corrupt = packet;
+            corrupt[idx] ^= 0x01;
+            assert!(!validate_frame_structure(&corrupt), "index {idx}");
+        }
+        assert!(!validate_frame_structure(&packet[..9]));
+        assert!(!validate_frame_structure(&[packet, packet].concat()));
+        assert!(!validate_frame_structure(&[]));
+    }
+}
@@ -0,0 +1,383 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Fixed-capacity queues for batching commands.
+
+use crate::{Command, Error, RequestAck, Result};
+use embedded_io::Write;
+
+/// Fixed-capacity queue of commands, useful for building up a startup
+/// sequence on targets without an allocator.
+///
+/// ## Example
+/// ```no_run
+/// # fn a<W: embedded_io::Write>(mut uart: W) -> dfr0299::Result<()> {
+/// use dfr0299::{Command, CommandQueue, PlaybackSource};
+/// let mut queue = CommandQueue::<4>::new();
+/// queue.push(Command::Reset)?;
+/// queue.push(Command::SetPlaybackSource(PlaybackSource::Tf))?;
+/// queue.push(Command::SetVolume(20))?;
+/// queue.push(Command::Track(1))?;
+/// queue.drain_to_writer(&mut uart)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CommandQueue<const N: usize> {
+    commands: heapless::Vec<Command, N>,
+}
+
+impl<const N: usize> CommandQueue<N> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a command to the end of the queue. Returns
+    /// `Error::QueueFull` if the queue is already at capacity.
+    pub fn push(&mut self, cmd: Command) -> Result<()> {
+        self.commands.push(cmd).map_err(|_| Error::QueueFull)
+    }
+
+    /// Add a command to the end of the queue.
+    ///
+    /// ## Panics
+    /// Panics if the queue is already at capacity.
+    pub fn push_unchecked(&mut self, cmd: Command) {
+        if self.commands.push(cmd).is_err() {
+            panic!("CommandQueue capacity ({N}) exceeded");
+        }
+    }
+
+    /// Number of queued commands
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    /// Whether the queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Remove all queued commands without sending them
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Serialise each queued command in order and write it to the
+    /// writer, removing it from the queue. If a write fails then the
+    /// commands which have not yet been written remain queued.
+    pub fn drain_to_writer<W: Write>(&mut self, writer: &mut W) -> Result<()> {
+        let mut sent = 0;
+        let mut result = Ok(());
+        for cmd in &self.commands {
+            result = crate::io::send_command(writer, cmd);
+            if result.is_err() {
+                break;
+            }
+            sent += 1;
+        }
+
+        self.commands.rotate_left(sent);
+        self.commands.truncate(self.commands.len() - sent);
+        result
+    }
+
+    /// Consume the queue, yielding each command serialised with the
+    /// given ACK flag
+    pub fn into_iter_with_ack(
+        self,
+        ack: impl Into<RequestAck>,
+    ) -> IntoPackets<N> {
+        IntoPackets {
+            commands: self.commands,
+            next: 0,
+            ack: ack.into(),
+        }
+    }
+
+    /// Iterate over the queued commands serialised without requesting
+    /// an ACK, leaving the queue intact
+    pub fn iter(&self) -> Packets<'_> {
+        Packets {
+            commands: self.commands.iter(),
+        }
+    }
+}
+
+/// Consuming iterator over the serialised packets of a `CommandQueue`
+#[derive(Clone, Debug)]
+pub struct IntoPackets<const N: usize> {
+    commands: heapless::Vec<Command, N>,
+    next: usize,
+    ack: RequestAck,
+}
+
+impl<const N: usize> Iterator for IntoPackets<N> {
+    type Item = [u8; 10];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cmd = self.commands.get(self.next)?;
+        self.next += 1;
+        Some(cmd.to_bytes_with_ack(self.ack))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.commands.len() - self.next;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<const N: usize> ExactSizeIterator for IntoPackets<N> {}
+
+/// Borrowing iterator over the serialised packets of a `CommandQueue`
+#[derive(Clone, Debug)]
+pub struct Packets<'a> {
+    commands: core::slice::Iter<'a, Command>,
+}
+
+impl Iterator for Packets<'_> {
+    type Item = [u8; 10];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.commands.next().map(Command::to_bytes)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.commands.size_hint()
+    }
+}
+
+impl ExactSizeIterator for Packets<'_> {}
+
+impl<const N: usize> IntoIterator for CommandQueue<N> {
+    type Item = [u8; 10];
+    type IntoIter = IntoPackets<N>;
+
+    /// Consume the queue, yielding each command serialised without
+    /// requesting an ACK
+    fn into_iter(self) -> Self::IntoIter {
+        self.into_iter_with_ack(RequestAck::No)
+    }
+}
+
+impl<'a, const N: usize> IntoIterator for &'a CommandQueue<N> {
+    type Item = [u8; 10];
+    type IntoIter = Packets<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Fixed-capacity queue with separate high and normal priority
+/// commands, so that e.g. an alarm can interrupt a queued playlist.
+/// Each priority holds up to `N` commands, and all of the high
+/// priority commands are popped before any normal priority ones.
+///
+/// ## Example
+/// ```
+/// use dfr0299::{Command, PriorityCommandQueue};
+/// let mut queue = PriorityCommandQueue::<4>::new();
+/// queue.push_normal(Command::Track(1))?;
+/// queue.push_normal(Command::Track(2))?;
+/// // the alarm fires
+/// queue.clear_normal();
+/// queue.push_high(Command::AdvertiseTrack(1))?;
+/// assert_eq!(queue.pop(), Some(Command::AdvertiseTrack(1)));
+/// assert_eq!(queue.pop(), None);
+/// # Ok::<(), dfr0299::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PriorityCommandQueue<const N: usize> {
+    high: heapless::Deque<Command, N>,
+    normal: heapless::Deque<Command, N>,
+}
+
+impl<const N: usize> PriorityCommandQueue<N> {
+    /// Create an empty queue
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a command to the end of the high priority queue. Returns
+    /// `Error::QueueFull` if it is already at capacity.
+    pub fn push_high(&mut self, cmd: Command) -> Result<()> {
+        self.high.push_back(cmd).map_err(|_| Error::QueueFull)
+    }
+
+    /// Add a command to the end of the normal priority queue. Returns
+    /// `Error::QueueFull` if it is already at capacity.
+    pub fn push_normal(&mut self, cmd: Command) -> Result<()> {
+        self.normal.push_back(cmd).map_err(|_| Error::QueueFull)
+    }
+
+    /// Remove the next command to send: the oldest high priority
+    /// command if there are any, otherwise the oldest normal priority
+    /// command
+    pub fn pop(&mut self) -> Option<Command> {
+        self.high.pop_front().or_else(|| self.normal.pop_front())
+    }
+
+    /// Total number of queued commands of both priorities
+    pub fn len(&self) -> usize {
+        self.high.len() + self.normal.len()
+    }
+
+    /// Whether there are no queued commands of either priority
+    pub fn is_empty(&self) -> bool {
+        self.high.is_empty() && self.normal.is_empty()
+    }
+
+    /// Number of queued high priority commands
+    pub fn high_priority_pending(&self) -> usize {
+        self.high.len()
+    }
+
+    /// Remove all of the normal priority commands, leaving the high
+    /// priority ones queued
+    pub fn clear_normal(&mut self) {
+        self.normal.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn drain_full_queue() {
+        let commands = [
+            Command::Reset,
+            Command::SetVolume(20),
+            Command::Next,
+            Command::Track(1),
+        ];
+        let mut queue = CommandQueue::<4>::new();
+        for cmd in commands {
+            queue.push(cmd).unwrap();
+        }
+        assert_eq!(queue.len(), 4);
+
+        let mut written = Vec::new();
+        queue.drain_to_writer(&mut written).unwrap();
+        assert!(queue.is_empty());
+
+        let mut expected = Vec::new();
+        for cmd in commands {
+            let mut buf = [0u8; 10];
+            cmd.serialise(&mut buf).unwrap();
+            expected.extend_from_slice(&buf);
+        }
+        assert_eq!(written, expected);
+    }
+
+    #[test]
+    fn iterate_packets() {
+        let mut queue = CommandQueue::<4>::new();
+        for cmd in [Command::Reset, Command::SetVolume(20), Command::Next] {
+            queue.push(cmd).unwrap();
+        }
+
+        let borrowed: Vec<[u8; 10]> = (&queue).into_iter().collect();
+        assert_eq!(queue.iter().len(), 3);
+        assert_eq!(queue.len(), 3);
+
+        let mut drained = Vec::new();
+        queue.clone().drain_to_writer(&mut drained).unwrap();
+        assert_eq!(borrowed.concat(), drained);
+
+        let with_ack: Vec<_> =
+            queue.clone().into_iter_with_ack(RequestAck::Yes).collect();
+        for (packet, plain) in with_ack.iter().zip(&borrowed) {
+            assert_eq!(packet[4], 0x01);
+            assert_eq!(plain[4], 0x00);
+        }
+
+        let consumed: Vec<_> = queue.into_iter().collect();
+        assert_eq!(consumed, borrowed);
+    }
+
+    #[test]
+    fn queue_full() {
+        let mut queue = CommandQueue::<2>::new();
+        queue.push(Command::Reset).unwrap();
+        queue.push_unchecked(Command::Next);
+        assert_eq!(queue.push(Command::Previous), Err(Error::QueueFull));
+        assert_eq!(queue.len(), 2);
+
+        queue.clear();
+        assert!(queue.is_empty());
+        queue.push(Command::Previous).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn push_unchecked_panics_when_full() {
+        let mut queue = CommandQueue::<1>::new();
+        queue.push_unchecked(Command::Reset);
+        queue.push_unchecked(Command::Next);
+    }
+
+    #[test]
+    fn failed_write_keeps_remaining_commands() {
+        let mut queue = CommandQueue::<3>::new();
+        queue.push(Command::Reset).unwrap();
+        queue.push(Command::Next).unwrap();
+        queue.push(Command::Previous).unwrap();
+
+        // room for one and a half packets
+        let mut buf = [0u8; 15];
+        let mut writer = &mut buf[..];
+        assert_eq!(queue.drain_to_writer(&mut writer), Err(Error::IoWrite));
+        assert_eq!(queue.len(), 2);
+    }
+
+    #[test]
+    fn priority_queue_order() {
+        let mut queue = PriorityCommandQueue::<3>::new();
+        assert!(queue.is_empty());
+        queue.push_normal(Command::Track(1)).unwrap();
+        queue.push_normal(Command::Track(2)).unwrap();
+        queue.push_high(Command::Stop).unwrap();
+        queue.push_normal(Command::Track(3)).unwrap();
+        queue.push_high(Command::AdvertiseTrack(4)).unwrap();
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.high_priority_pending(), 2);
+
+        assert_eq!(queue.pop(), Some(Command::Stop));
+        assert_eq!(queue.pop(), Some(Command::AdvertiseTrack(4)));
+        assert_eq!(queue.high_priority_pending(), 0);
+        assert_eq!(queue.pop(), Some(Command::Track(1)));
+        // a high priority command jumps ahead of the rest of the
+        // playlist
+        queue.push_high(Command::Pause).unwrap();
+        assert_eq!(queue.pop(), Some(Command::Pause));
+        assert_eq!(queue.pop(), Some(Command::Track(2)));
+        assert_eq!(queue.pop(), Some(Command::Track(3)));
+        assert_eq!(queue.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn priority_queue_capacity() {
+        let mut queue = PriorityCommandQueue::<2>::new();
+        for _ in 0..2 {
+            queue.push_high(Command::Stop).unwrap();
+            queue.push_normal(Command::Next).unwrap();
+        }
+        assert_eq!(queue.push_high(Command::Stop), Err(Error::QueueFull));
+        assert_eq!(queue.push_normal(Command::Next), Err(Error::QueueFull));
+        assert_eq!(queue.len(), 4);
+
+        queue.clear_normal();
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.high_priority_pending(), 2);
+        queue.push_normal(Command::Track(1)).unwrap();
+        assert_eq!(queue.pop(), Some(Command::Stop));
+        assert_eq!(queue.pop(), Some(Command::Stop));
+        assert_eq!(queue.pop(), Some(Command::Track(1)));
+    }
+}
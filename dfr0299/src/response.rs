@@ -2,13 +2,17 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, you can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{Error, Result};
+use crate::proto::{LEN, START, STOP, VERSION};
+use crate::{calculate_checksum, Error, Packet, PlaybackSource, Result};
+use core::fmt::{self, Display, Formatter};
 use num_enum::TryFromPrimitive;
 
 /// Possible messages we may receive from the DFR0299.
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 pub enum Response {
     /// Response to any command that has the "request ACK" field set to
     /// `true`. Not documented in the datasheet, but present in the
@@ -20,10 +24,10 @@ pub enum Response {
     /// "//handle the 0x41 ack feedback as a spcecial case, in case the
     /// pollusion of _handleCommand, _handleParameter, and _handleType."
     Ack,
-    /// Report that the specified disk is connected and online.
-    /// Attempting to play a track before the device sends this message
-    /// may or may not be successfull.
-    DiskOnline(Disk),
+    /// Report which disks are connected and online. Attempting to play
+    /// a track before the device sends this message may or may not be
+    /// successfull.
+    DiskOnline(DiskSet),
     /// Report that playback is complete from the UDisk source
     UDiskFinishPlayback(u16),
     /// Report that playback is complete from the SD card source
@@ -43,8 +47,10 @@ pub enum Response {
 /// Disk types that the device might report the status of. Note that
 /// the definitions here are slightly different to those of
 /// `Control::PlaybackSource`.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, TryFromPrimitive)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 #[repr(u8)]
 #[allow(missing_docs)]
 pub enum Disk {
@@ -57,18 +63,202 @@ pub enum Disk {
     UDiskAndFlash = 0x05,
 }
 
+bitflags::bitflags! {
+    /// Set of disks reported by `Response::DiskOnline`. Unlike the
+    /// other disk notifications the device reports all of the online
+    /// disks at once, with one bit per disk.
+    #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+    #[cfg_attr(
+        feature = "use_serde",
+        derive(serde::Serialize, serde::Deserialize)
+    )]
+    #[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
+    pub struct DiskSet: u8 {
+        /// USB disk
+        const UDISK = 0x01;
+        /// SD card
+        const TF = 0x02;
+        /// PC
+        const PC = 0x04;
+        /// Flash chip
+        const FLASH = 0x08;
+    }
+}
+
+impl Disk {
+    const ALL: [Self; 5] = [
+        Self::UDisk,
+        Self::Tf,
+        Self::Pc,
+        Self::Flash,
+        Self::UDiskAndFlash,
+    ];
+
+    /// All of the disk types in order
+    pub fn all() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter()
+    }
+
+    /// Look up a disk from the full 16-bit parameter of a packet.
+    /// Returns `Error::InvalidParameterValue` if the high byte is
+    /// non-zero or the low byte isn't a known disk.
+    pub fn try_from_param(param: u16) -> Result<Self> {
+        let disk = u8::try_from(param)
+            .map_err(|_| Error::InvalidParameterValue(param))?;
+        Ok(Self::try_from(disk)?)
+    }
+
+    /// Look up a disk from the low byte of a packet's parameter,
+    /// ignoring the high byte, for firmware which sets it to something
+    /// other than zero
+    pub fn try_from_param_permissive(param: u16) -> Result<Self> {
+        Ok(Self::try_from(param as u8)?)
+    }
+
+    /// The playback source which plays from this disk. `Pc` and
+    /// `UDiskAndFlash` have no equivalent source.
+    pub fn to_playback_source(self) -> Option<PlaybackSource> {
+        match self {
+            Disk::UDisk => Some(PlaybackSource::UDisk),
+            Disk::Tf => Some(PlaybackSource::Tf),
+            Disk::Flash => Some(PlaybackSource::Flash),
+            Disk::Pc | Disk::UDiskAndFlash => None,
+        }
+    }
+}
+
+/// A disk equals the playback source which plays from it, see
+/// `Disk::to_playback_source`
+impl PartialEq<PlaybackSource> for Disk {
+    fn eq(&self, source: &PlaybackSource) -> bool {
+        self.to_playback_source() == Some(*source)
+    }
+}
+
+/// A playback source equals the disk which it plays from, see
+/// `PlaybackSource::to_disk`
+impl PartialEq<Disk> for PlaybackSource {
+    fn eq(&self, disk: &Disk) -> bool {
+        disk == self
+    }
+}
+
+impl From<Disk> for DiskSet {
+    fn from(disk: Disk) -> Self {
+        match disk {
+            Disk::UDisk => Self::UDISK,
+            Disk::Tf => Self::TF,
+            Disk::Pc => Self::PC,
+            Disk::Flash => Self::FLASH,
+            Disk::UDiskAndFlash => Self::UDISK | Self::FLASH,
+        }
+    }
+}
+
+impl Display for Disk {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Disk::UDisk => "USB disk",
+            Disk::Tf => "SD card",
+            Disk::Pc => "PC",
+            Disk::Flash => "Flash",
+            Disk::UDiskAndFlash => "USB+Flash",
+        })
+    }
+}
+
+impl Display for DiskSet {
+    /// List the disks in the set separated by commas, or "none" if the
+    /// set is empty
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return fmt.write_str("none");
+        }
+        for (idx, (_, disk)) in [
+            (Self::UDISK, Disk::UDisk),
+            (Self::TF, Disk::Tf),
+            (Self::PC, Disk::Pc),
+            (Self::FLASH, Disk::Flash),
+        ]
+        .into_iter()
+        .filter(|(flag, _)| self.contains(*flag))
+        .enumerate()
+        {
+            if idx > 0 {
+                fmt.write_str(", ")?;
+            }
+            write!(fmt, "{disk}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "use_defmt")]
+impl defmt::Format for DiskSet {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "DiskSet({=u8:#04x})", self.bits());
+    }
+}
+
 /// Possible error states reported by the device
-#[derive(Copy, Clone, Debug, Eq, PartialEq, TryFromPrimitive)]
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 #[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
-#[repr(u8)]
+#[cfg_attr(feature = "use_serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "use_arbitrary", derive(arbitrary::Arbitrary))]
 #[allow(missing_docs)]
 pub enum ModuleErrorType {
-    Busy = 0x00,
-    IncompleteFrameReceived = 0x01,
+    Busy,
+    IncompleteFrameReceived,
     /// I don't know when this error is returned, as the module didn't
     /// send it when I intentionally sent it a packet with an invalid
     /// checksum
-    ChecksumError = 0x02,
+    ChecksumError,
+    /// An error code not listed in the datasheet, as sent by some clone
+    /// modules. Contains the raw error code.
+    Unknown(u8),
+}
+
+impl ModuleErrorType {
+    fn description(self) -> &'static str {
+        match self {
+            ModuleErrorType::Busy => "Busy",
+            ModuleErrorType::IncompleteFrameReceived => "Incomplete frame",
+            ModuleErrorType::ChecksumError => "Checksum error",
+            ModuleErrorType::Unknown(_) => "Unknown error",
+        }
+    }
+}
+
+impl From<u8> for ModuleErrorType {
+    fn from(code: u8) -> Self {
+        match code {
+            0x00 => Self::Busy,
+            0x01 => Self::IncompleteFrameReceived,
+            0x02 => Self::ChecksumError,
+            code => Self::Unknown(code),
+        }
+    }
+}
+
+impl From<ModuleErrorType> for u8 {
+    fn from(err: ModuleErrorType) -> Self {
+        match err {
+            ModuleErrorType::Busy => 0x00,
+            ModuleErrorType::IncompleteFrameReceived => 0x01,
+            ModuleErrorType::ChecksumError => 0x02,
+            ModuleErrorType::Unknown(code) => code,
+        }
+    }
+}
+
+impl Display for ModuleErrorType {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(self.description())?;
+        if let ModuleErrorType::Unknown(code) = self {
+            write!(fmt, " {code:#04x}")?;
+        }
+        Ok(())
+    }
 }
 
 impl Response {
@@ -78,21 +268,219 @@ impl Response {
     /// any known command then the byte is returned with an
     /// `Error::InvalidCommand`.
     pub fn parse(cmd: u8, param_h: u8, param_l: u8) -> Result<Self> {
+        Self::parse_with_feedback(cmd, 0x00, param_h, param_l)
+            .map(|(response, _)| response)
+    }
+
+    /// Parse a response as with `Response::parse`, also returning the
+    /// feedback byte of the packet. For `Response::Ack` this is
+    /// expected to be the command byte being acknowledged, according
+    /// to the official arduino library.
+    pub fn parse_with_feedback(
+        cmd: u8,
+        feedback: u8,
+        param_h: u8,
+        param_l: u8,
+    ) -> Result<(Self, u8)> {
         use Response::*;
 
         let param = u16::from_be_bytes([param_h, param_l]);
 
-        Ok(match cmd {
-            0x40 => ModuleError(ModuleErrorType::try_from(param_l)?),
-            0x3a => DiskInserted(Disk::try_from(param_l)?),
-            0x3b => DiskRemoved(Disk::try_from(param_l)?),
+        let response = match cmd {
+            0x40 => ModuleError(ModuleErrorType::from(param_l)),
+            0x3a => DiskInserted(Disk::try_from_param(param)?),
+            0x3b => DiskRemoved(Disk::try_from_param(param)?),
             0x3c => UDiskFinishPlayback(param),
             0x3d => TfFinishPlayback(param),
             0x3e => FlashFinishPlayback(param),
-            0x3f => DiskOnline(Disk::try_from(param_l)?),
+            0x3f => DiskOnline(
+                u8::try_from(param)
+                    .ok()
+                    .and_then(DiskSet::from_bits)
+                    .ok_or(Error::InvalidParameterValue(param))?,
+            ),
             0x41 => Ack,
             cmd => return Err(Error::InvalidCommand(cmd)),
-        })
+        };
+        Ok((response, feedback))
+    }
+
+    /// Encode the response as the packet the device would send. This is
+    /// the inverse of `Response::try_from`.
+    pub fn to_bytes(self) -> [u8; 10] {
+        use Response::*;
+
+        let (cmd, param) = match self {
+            Ack => (0x41, 0),
+            DiskOnline(disks) => (0x3f, u16::from(disks.bits())),
+            UDiskFinishPlayback(track) => (0x3c, track),
+            TfFinishPlayback(track) => (0x3d, track),
+            FlashFinishPlayback(track) => (0x3e, track),
+            ModuleError(err) => (0x40, u16::from(u8::from(err))),
+            DiskInserted(disk) => (0x3a, u16::from(disk as u8)),
+            DiskRemoved(disk) => (0x3b, u16::from(disk as u8)),
+        };
+        let [param_h, param_l] = param.to_be_bytes();
+        let [checksum_h, checksum_l] =
+            calculate_checksum(VERSION, LEN, cmd, 0x00, param_h, param_l)
+                .to_be_bytes();
+        [
+            START, VERSION, LEN, cmd, 0x00, param_h, param_l, checksum_h,
+            checksum_l, STOP,
+        ]
+    }
+
+    /// The disk which a disk notification refers to. For
+    /// `DiskOnline` this is only `Some` if the set of online disks
+    /// corresponds to a single `Disk`, i.e. one disk or
+    /// `Disk::UDiskAndFlash`.
+    pub fn disk(&self) -> Option<Disk> {
+        use Response::*;
+        match self {
+            DiskInserted(disk) | DiskRemoved(disk) => Some(*disk),
+            DiskOnline(disks) => {
+                Disk::all().find(|disk| DiskSet::from(*disk) == *disks)
+            }
+            _ => None,
+        }
+    }
+
+    /// The track number reported by a playback complete notification
+    pub fn track_number(&self) -> Option<u16> {
+        use Response::*;
+        match self {
+            UDiskFinishPlayback(track)
+            | TfFinishPlayback(track)
+            | FlashFinishPlayback(track) => Some(*track),
+            _ => None,
+        }
+    }
+
+    /// The error reported by `Response::ModuleError`
+    pub fn error_type(&self) -> Option<ModuleErrorType> {
+        match self {
+            Response::ModuleError(err) => Some(*err),
+            _ => None,
+        }
+    }
+
+    /// Whether this is `Response::Ack`
+    pub fn is_ack(&self) -> bool {
+        matches!(self, Response::Ack)
+    }
+
+    /// Whether this reports a disk being online, inserted or removed
+    pub fn is_disk_event(&self) -> bool {
+        use Response::*;
+        matches!(self, DiskOnline(_) | DiskInserted(_) | DiskRemoved(_))
+    }
+
+    /// Whether this reports that playback from one of the sources is
+    /// complete
+    pub fn is_playback_complete(&self) -> bool {
+        self.track_number().is_some()
+    }
+}
+
+impl Display for Response {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        use Response::*;
+        match self {
+            Ack => fmt.write_str("ACK"),
+            DiskOnline(disks) if disks.is_empty() => {
+                fmt.write_str("No disks online")
+            }
+            DiskOnline(disks) => write!(fmt, "{disks} online"),
+            UDiskFinishPlayback(track) => {
+                write!(fmt, "{}: finished track {track}", Disk::UDisk)
+            }
+            TfFinishPlayback(track) => {
+                write!(fmt, "{}: finished track {track}", Disk::Tf)
+            }
+            FlashFinishPlayback(track) => {
+                write!(fmt, "{}: finished track {track}", Disk::Flash)
+            }
+            ModuleError(ModuleErrorType::Unknown(code)) => {
+                write!(fmt, "Module error: unknown error {code:#04x}")
+            }
+            ModuleError(err) => {
+                // lowercase the first letter without allocating
+                let mut chars = err.description().chars();
+                let first = chars.next().unwrap_or_default();
+                write!(
+                    fmt,
+                    "Module error: {}{}",
+                    first.to_ascii_lowercase(),
+                    chars.as_str()
+                )
+            }
+            DiskInserted(disk) => write!(fmt, "{disk} inserted"),
+            DiskRemoved(disk) => write!(fmt, "{disk} removed"),
+        }
+    }
+}
+
+#[cfg(feature = "heapless")]
+impl Response {
+    /// The `Display` description of the response in a fixed-capacity
+    /// string, e.g. for showing on a small screen. Descriptions longer
+    /// than 64 bytes are truncated.
+    pub fn to_heapless_string(&self) -> heapless::String<64> {
+        display_truncated(self)
+    }
+}
+
+/// Format `value` into a fixed-capacity string, truncating it at a
+/// character boundary if it doesn't fit
+#[cfg(feature = "heapless")]
+pub(crate) fn display_truncated<const N: usize>(
+    value: &impl Display,
+) -> heapless::String<N> {
+    struct Truncate<'a, const N: usize>(&'a mut heapless::String<N>);
+
+    impl<const N: usize> fmt::Write for Truncate<'_, N> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for c in s.chars() {
+                // stop writing once full, without failing the format
+                if self.0.push(c).is_err() {
+                    break;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut out = heapless::String::new();
+    let _ =
+        fmt::Write::write_fmt(&mut Truncate(&mut out), format_args!("{value}"));
+    out
+}
+
+impl From<Response> for [u8; 10] {
+    fn from(resp: Response) -> Self {
+        resp.to_bytes()
+    }
+}
+
+impl TryFrom<[u8; 10]> for Response {
+    type Error = Error;
+
+    /// Parse a complete response packet, validating its framing and
+    /// checksum
+    fn try_from(buf: [u8; 10]) -> Result<Self> {
+        Packet::from_bytes(&buf)?.as_response()
+    }
+}
+
+impl TryFrom<&[u8]> for Response {
+    type Error = Error;
+
+    /// Parse a complete response packet, returning
+    /// `Error::BufferTooShort` if the slice is not exactly 10 bytes long
+    fn try_from(buf: &[u8]) -> Result<Self> {
+        let buf: [u8; 10] =
+            buf.try_into().map_err(|_| Error::BufferTooShort)?;
+        Self::try_from(buf)
     }
 }
 
@@ -110,6 +498,424 @@ mod test {
         assert_eq!(resp, Response::DiskInserted(Disk::Tf));
     }
 
+    #[test]
+    fn parse_with_feedback() {
+        assert_eq!(
+            Response::parse_with_feedback(0x41, 0x03, 0x00, 0x00),
+            Ok((Response::Ack, 0x03))
+        );
+        assert_eq!(
+            Response::parse_with_feedback(0x3a, 0x01, 0x00, 0x02),
+            Ok((Response::DiskInserted(Disk::Tf), 0x01))
+        );
+        assert_eq!(
+            Response::parse_with_feedback(0x30, 0x01, 0x00, 0x00),
+            Err(Error::InvalidCommand(0x30))
+        );
+    }
+
+    #[test]
+    fn parse_packets() {
+        let tf_insert =
+            [0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x02, 0xfe, 0xbf, 0xef];
+        let udisk_remove =
+            [0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef];
+        for (packet, expected) in [
+            (tf_insert, Response::DiskInserted(Disk::Tf)),
+            (udisk_remove, Response::DiskRemoved(Disk::UDisk)),
+        ] {
+            assert_eq!(Response::try_from(packet), Ok(expected));
+            assert_eq!(Response::try_from(&packet[..]), Ok(expected));
+        }
+
+        assert_eq!(
+            Response::try_from(&tf_insert[..9]),
+            Err(Error::BufferTooShort)
+        );
+        assert_eq!(
+            Response::try_from(&[tf_insert, udisk_remove].concat()[..]),
+            Err(Error::BufferTooShort)
+        );
+
+        let mut bad_checksum = tf_insert;
+        bad_checksum[8] = 0x00;
+        assert_eq!(
+            Response::try_from(bad_checksum),
+            Err(Error::BadChecksum {
+                expected: [0xfe, 0xbf],
+                received: [0xfe, 0x00],
+            })
+        );
+
+        let unknown =
+            [0x7e, 0xff, 0x06, 0x30, 0x00, 0x00, 0x00, 0xfe, 0xcb, 0xef];
+        assert_eq!(
+            Response::try_from(&unknown[..]),
+            Err(Error::InvalidCommand(0x30))
+        );
+    }
+
+    #[test]
+    fn to_bytes_round_trip() {
+        use Response::*;
+
+        let disks = [
+            Disk::UDisk,
+            Disk::Tf,
+            Disk::Pc,
+            Disk::Flash,
+            Disk::UDiskAndFlash,
+        ];
+        let mut responses = vec![
+            Ack,
+            DiskOnline(DiskSet::empty()),
+            DiskOnline(DiskSet::all()),
+            ModuleError(ModuleErrorType::Busy),
+            ModuleError(ModuleErrorType::IncompleteFrameReceived),
+            ModuleError(ModuleErrorType::ChecksumError),
+        ];
+        for track in [0, 1, 0x1234, 0xffff] {
+            responses.push(UDiskFinishPlayback(track));
+            responses.push(TfFinishPlayback(track));
+            responses.push(FlashFinishPlayback(track));
+        }
+        for disk in disks {
+            responses.push(DiskOnline(disk.into()));
+            responses.push(DiskInserted(disk));
+            responses.push(DiskRemoved(disk));
+        }
+
+        for resp in responses {
+            let packet = resp.to_bytes();
+            assert_eq!(<[u8; 10]>::from(resp), packet);
+            assert_eq!(Response::try_from(packet), Ok(resp), "{packet:02x?}");
+        }
+
+        assert_eq!(
+            Response::DiskInserted(Disk::Tf).to_bytes(),
+            [0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x02, 0xfe, 0xbf, 0xef]
+        );
+    }
+
+    #[test]
+    fn display() {
+        use Response::*;
+        for (resp, expected) in [
+            (Ack, "ACK"),
+            (DiskOnline(Disk::Tf.into()), "SD card online"),
+            (
+                DiskOnline(Disk::UDiskAndFlash.into()),
+                "USB disk, Flash online",
+            ),
+            (
+                DiskOnline(DiskSet::all()),
+                "USB disk, SD card, PC, Flash online",
+            ),
+            (DiskOnline(DiskSet::empty()), "No disks online"),
+            (UDiskFinishPlayback(1), "USB disk: finished track 1"),
+            (TfFinishPlayback(3), "SD card: finished track 3"),
+            (FlashFinishPlayback(300), "Flash: finished track 300"),
+            (ModuleError(ModuleErrorType::Busy), "Module error: busy"),
+            (
+                ModuleError(ModuleErrorType::IncompleteFrameReceived),
+                "Module error: incomplete frame",
+            ),
+            (
+                ModuleError(ModuleErrorType::ChecksumError),
+                "Module error: checksum error",
+            ),
+            (
+                ModuleError(ModuleErrorType::Unknown(0x03)),
+                "Module error: unknown error 0x03",
+            ),
+            (DiskInserted(Disk::Tf), "SD card inserted"),
+            (DiskRemoved(Disk::UDiskAndFlash), "USB+Flash removed"),
+        ] {
+            assert_eq!(resp.to_string(), expected);
+        }
+
+        for (disk, expected) in [
+            (Disk::UDisk, "USB disk"),
+            (Disk::Tf, "SD card"),
+            (Disk::Pc, "PC"),
+            (Disk::Flash, "Flash"),
+            (Disk::UDiskAndFlash, "USB+Flash"),
+        ] {
+            assert_eq!(disk.to_string(), expected);
+        }
+        assert_eq!(DiskSet::empty().to_string(), "none");
+
+        for (err, expected) in [
+            (ModuleErrorType::Busy, "Busy"),
+            (ModuleErrorType::IncompleteFrameReceived, "Incomplete frame"),
+            (ModuleErrorType::ChecksumError, "Checksum error"),
+            (ModuleErrorType::Unknown(0xff), "Unknown error 0xff"),
+        ] {
+            assert_eq!(err.to_string(), expected);
+        }
+    }
+
+    #[cfg(feature = "heapless")]
+    #[test]
+    fn heapless_strings() {
+        use Response::*;
+        for resp in [
+            Ack,
+            DiskOnline(DiskSet::all()),
+            TfFinishPlayback(3),
+            ModuleError(ModuleErrorType::IncompleteFrameReceived),
+            DiskRemoved(Disk::UDiskAndFlash),
+        ] {
+            assert_eq!(resp.to_heapless_string(), resp.to_string().as_str());
+        }
+
+        let long = "USB disk, SD card, PC, Flash online";
+        assert_eq!(display_truncated::<64>(&long), long);
+        assert_eq!(display_truncated::<8>(&long), "USB disk");
+        assert_eq!(display_truncated::<0>(&long), "");
+        // truncation happens on a character boundary
+        assert_eq!(display_truncated::<3>(&"ab\u{e9}"), "ab");
+    }
+
+    #[test]
+    fn module_error_codes() {
+        use ModuleErrorType::*;
+        for (code, expected) in [
+            (0x00, Busy),
+            (0x01, IncompleteFrameReceived),
+            (0x02, ChecksumError),
+            (0x03, Unknown(0x03)),
+            (0xff, Unknown(0xff)),
+        ] {
+            assert_eq!(
+                Response::parse(0x40, 0x00, code),
+                Ok(Response::ModuleError(expected))
+            );
+            assert_eq!(u8::from(expected), code);
+            let packet = Response::ModuleError(expected).to_bytes();
+            assert_eq!(
+                Response::try_from(packet),
+                Ok(Response::ModuleError(expected))
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_parameter_values() {
+        assert_eq!(
+            Disk::try_from(0xff).map_err(Error::from),
+            Err(Error::InvalidParameterValue(0xff))
+        );
+        assert_eq!(
+            Response::parse(0x3a, 0x00, 0x07),
+            Err(Error::InvalidParameterValue(0x07))
+        );
+        assert_eq!(
+            Response::parse(0x3f, 0x00, 0x10),
+            Err(Error::InvalidParameterValue(0x10))
+        );
+        assert_eq!(
+            Error::InvalidParameterValue(0x0a).to_string(),
+            "InvalidParameterValue(0x000a)"
+        );
+        assert_eq!(
+            Error::InvalidParameterValue(0x0102).to_string(),
+            "InvalidParameterValue(0x0102)"
+        );
+    }
+
+    #[test]
+    fn disk_all() {
+        assert_eq!(Disk::all().count(), 5);
+        for (i, disk) in Disk::all().enumerate() {
+            assert_eq!(disk as usize, i + 1);
+        }
+    }
+
+    #[test]
+    fn accessors() {
+        use Response::*;
+        let expected = [
+            (Ack, None, None, None),
+            (DiskOnline(DiskSet::TF), Some(Disk::Tf), None, None),
+            (
+                DiskOnline(DiskSet::UDISK | DiskSet::FLASH),
+                Some(Disk::UDiskAndFlash),
+                None,
+                None,
+            ),
+            (DiskOnline(DiskSet::UDISK | DiskSet::TF), None, None, None),
+            (DiskOnline(DiskSet::empty()), None, None, None),
+            (UDiskFinishPlayback(1), None, Some(1), None),
+            (TfFinishPlayback(0x1234), None, Some(0x1234), None),
+            (FlashFinishPlayback(2999), None, Some(2999), None),
+            (
+                ModuleError(ModuleErrorType::Busy),
+                None,
+                None,
+                Some(ModuleErrorType::Busy),
+            ),
+            (DiskInserted(Disk::Pc), Some(Disk::Pc), None, None),
+            (DiskRemoved(Disk::Flash), Some(Disk::Flash), None, None),
+        ];
+        for (resp, disk, track, err) in expected {
+            assert_eq!(resp.disk(), disk, "{resp:?}");
+            assert_eq!(resp.track_number(), track, "{resp:?}");
+            assert_eq!(resp.error_type(), err, "{resp:?}");
+            assert_eq!(resp.is_ack(), resp == Ack, "{resp:?}");
+            assert_eq!(
+                resp.is_disk_event(),
+                matches!(
+                    resp,
+                    DiskOnline(_) | DiskInserted(_) | DiskRemoved(_)
+                ),
+                "{resp:?}"
+            );
+            assert_eq!(
+                resp.is_playback_complete(),
+                track.is_some(),
+                "{resp:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn disk_from_param() {
+        for disk in Disk::all() {
+            let param = u16::from(disk as u8);
+            assert_eq!(Disk::try_from_param(param), Ok(disk));
+            assert_eq!(Disk::try_from_param_permissive(param), Ok(disk));
+            assert_eq!(
+                Disk::try_from_param_permissive(param | 0x0100),
+                Ok(disk)
+            );
+        }
+        assert_eq!(
+            Disk::try_from_param(0x0102),
+            Err(Error::InvalidParameterValue(0x0102))
+        );
+        assert_eq!(
+            Disk::try_from_param(0x0006),
+            Err(Error::InvalidParameterValue(0x06))
+        );
+        assert_eq!(
+            Disk::try_from_param_permissive(0x0100),
+            Err(Error::InvalidParameterValue(0x00))
+        );
+
+        // the high byte of the disk notifications must be zero
+        assert_eq!(
+            Response::parse(0x3a, 0x00, 0x02),
+            Ok(Response::DiskInserted(Disk::Tf))
+        );
+        assert_eq!(
+            Response::parse(0x3b, 0x01, 0x02),
+            Err(Error::InvalidParameterValue(0x0102))
+        );
+        assert_eq!(
+            Response::parse(0x3f, 0x00, 0x02),
+            Ok(Response::DiskOnline(DiskSet::TF))
+        );
+        assert_eq!(
+            Response::parse(0x3f, 0x01, 0x02),
+            Err(Error::InvalidParameterValue(0x0102))
+        );
+    }
+
+    #[test]
+    fn disk_to_playback_source() {
+        for (disk, source) in [
+            (Disk::UDisk, Some(PlaybackSource::UDisk)),
+            (Disk::Tf, Some(PlaybackSource::Tf)),
+            (Disk::Pc, None),
+            (Disk::Flash, Some(PlaybackSource::Flash)),
+            (Disk::UDiskAndFlash, None),
+        ] {
+            assert_eq!(disk.to_playback_source(), source);
+            if let Some(source) = source {
+                assert_eq!(source.to_disk(), Some(disk));
+            }
+        }
+    }
+
+    #[test]
+    fn disk_playback_source_eq() {
+        let disks = [
+            Disk::UDisk,
+            Disk::Tf,
+            Disk::Pc,
+            Disk::Flash,
+            Disk::UDiskAndFlash,
+        ];
+        let sources = [
+            PlaybackSource::UDisk,
+            PlaybackSource::Tf,
+            PlaybackSource::Aux,
+            PlaybackSource::Sleep,
+            PlaybackSource::Flash,
+        ];
+        let matching = [
+            (Disk::UDisk, PlaybackSource::UDisk),
+            (Disk::Tf, PlaybackSource::Tf),
+            (Disk::Flash, PlaybackSource::Flash),
+        ];
+        for disk in disks {
+            for source in sources {
+                let expected = matching.contains(&(disk, source));
+                assert_eq!(disk == source, expected, "{disk:?} {source:?}");
+                assert_eq!(source == disk, expected, "{source:?} {disk:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn hash_map_keys() {
+        use std::collections::HashMap;
+
+        let mut counts = HashMap::new();
+        for resp in [
+            Response::Ack,
+            Response::DiskInserted(Disk::Tf),
+            Response::DiskOnline(DiskSet::TF | DiskSet::UDISK),
+            Response::Ack,
+            Response::DiskInserted(Disk::Tf),
+            Response::DiskInserted(Disk::UDisk),
+            Response::ModuleError(ModuleErrorType::Busy),
+            Response::DiskOnline(DiskSet::UDISK | DiskSet::TF),
+        ] {
+            *counts.entry(resp).or_insert(0) += 1;
+        }
+        assert_eq!(counts.len(), 5);
+        assert_eq!(counts[&Response::Ack], 2);
+        assert_eq!(counts[&Response::DiskInserted(Disk::Tf)], 2);
+        assert_eq!(counts[&Response::DiskInserted(Disk::UDisk)], 1);
+        assert_eq!(
+            counts[&Response::DiskOnline(DiskSet::TF | DiskSet::UDISK)],
+            2
+        );
+        assert_eq!(counts[&Response::ModuleError(ModuleErrorType::Busy)], 1);
+    }
+
+    #[test]
+    fn parse_disk_online() {
+        let resp = Response::parse(0x3f, 0x00, 0x02).unwrap();
+        assert_eq!(resp, Response::DiskOnline(DiskSet::TF));
+        assert_eq!(resp, Response::DiskOnline(Disk::Tf.into()));
+
+        let resp = Response::parse(0x3f, 0x00, 0x0b).unwrap();
+        assert_eq!(
+            resp,
+            Response::DiskOnline(DiskSet::UDISK | DiskSet::TF | DiskSet::FLASH)
+        );
+        let Response::DiskOnline(disks) = resp else {
+            unreachable!()
+        };
+        assert!(disks.contains(Disk::UDiskAndFlash.into()));
+        assert!(!disks.contains(DiskSet::PC));
+
+        assert!(Response::parse(0x3f, 0x00, 0x10).is_err());
+    }
+
     #[test]
     fn parse_checksum_error() {
         let cmd = 0x40;
@@ -119,4 +925,50 @@ mod test {
         let resp = Response::parse(cmd, param_h, param_l).unwrap();
         assert_eq!(resp, Response::ModuleError(ModuleErrorType::ChecksumError));
     }
+
+    #[cfg(feature = "use_serde")]
+    #[test]
+    fn serde_round_trip() {
+        use crate::assert_serde_round_trip;
+        use Response::*;
+
+        let disks = [
+            Disk::UDisk,
+            Disk::Tf,
+            Disk::Pc,
+            Disk::Flash,
+            Disk::UDiskAndFlash,
+        ];
+        let errors = [
+            ModuleErrorType::Busy,
+            ModuleErrorType::IncompleteFrameReceived,
+            ModuleErrorType::ChecksumError,
+            ModuleErrorType::Unknown(0x03),
+        ];
+
+        assert_serde_round_trip(Ack);
+        assert_serde_round_trip(DiskOnline(DiskSet::empty()));
+        assert_serde_round_trip(DiskOnline(DiskSet::all()));
+        for track in [0, 1, 0xffff] {
+            assert_serde_round_trip(UDiskFinishPlayback(track));
+            assert_serde_round_trip(TfFinishPlayback(track));
+            assert_serde_round_trip(FlashFinishPlayback(track));
+        }
+        for disk in disks {
+            assert_serde_round_trip(disk);
+            assert_serde_round_trip(DiskOnline(disk.into()));
+            assert_serde_round_trip(DiskInserted(disk));
+            assert_serde_round_trip(DiskRemoved(disk));
+        }
+        for err in errors {
+            assert_serde_round_trip(err);
+            assert_serde_round_trip(ModuleError(err));
+        }
+
+        assert_eq!(serde_json::to_string(&Ack).unwrap(), r#""Ack""#);
+        assert_eq!(
+            serde_json::to_string(&DiskInserted(Disk::Tf)).unwrap(),
+            r#"{"DiskInserted":"Tf"}"#
+        );
+    }
 }
@@ -0,0 +1,200 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Ring buffer in front of a `Parser`, for UARTs which receive via DMA.
+
+use crate::{Error, ParseResult, Parser, Response, Result};
+
+/// Fixed-capacity ring buffer of received bytes with a `Parser` to
+/// process them, for UARTs where a DMA interrupt hands over chunks of
+/// bytes which can't be parsed straight away. Bytes are added with
+/// `RingParser::write`, e.g. in the interrupt handler, and parsed later
+/// with `RingParser::drain`.
+///
+/// ## Example
+/// ```
+/// use dfr0299::{RingParser, Response};
+/// let mut ring = RingParser::<32>::new();
+/// let packet = Response::Ack.to_bytes();
+/// ring.write(&packet[..4])?;
+/// assert_eq!(ring.drain().count(), 0);
+/// ring.write(&packet[4..])?;
+/// assert_eq!(ring.drain().collect::<Vec<_>>(), [Ok(Response::Ack)]);
+/// # Ok::<(), dfr0299::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct RingParser<const N: usize> {
+    buf: [u8; N],
+    /// Index of the next byte to parse
+    read: usize,
+    /// Index at which the next received byte is stored
+    write: usize,
+    len: usize,
+    overflows: u32,
+    parser: Parser,
+}
+
+impl<const N: usize> Default for RingParser<N> {
+    fn default() -> Self {
+        Self {
+            buf: [0; N],
+            read: 0,
+            write: 0,
+            len: 0,
+            overflows: 0,
+            parser: Parser::new(),
+        }
+    }
+}
+
+impl<const N: usize> RingParser<N> {
+    /// Create an empty ring buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add received bytes to the buffer, returning the number of bytes
+    /// added. If there isn't room for all of them then as many as will
+    /// fit are added, the rest are dropped, and
+    /// `Error::BufferOverflow` is returned.
+    pub fn write(&mut self, data: &[u8]) -> Result<usize> {
+        let count = data.len().min(N - self.len);
+        for &byte in &data[..count] {
+            self.buf[self.write] = byte;
+            self.write = (self.write + 1) % N;
+        }
+        self.len += count;
+        if count < data.len() {
+            self.overflows = self.overflows.wrapping_add(1);
+            return Err(Error::BufferOverflow);
+        }
+        Ok(count)
+    }
+
+    /// Parse the buffered bytes, yielding each complete response or
+    /// parse error. A partial packet at the end of the buffer is kept
+    /// in the parser until more bytes are written. Bytes are only
+    /// removed from the buffer as the iterator is advanced.
+    pub fn drain(&mut self) -> impl Iterator<Item = Result<Response>> + '_ {
+        core::iter::from_fn(move || {
+            while let Some(byte) = self.pop() {
+                match self.parser.process_byte(byte) {
+                    Ok(ParseResult::Complete(resp)) => return Some(Ok(resp)),
+                    Ok(_) => {}
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            None
+        })
+    }
+
+    /// Number of calls to `RingParser::write` which dropped bytes
+    /// because the buffer was full
+    pub fn overflow_count(&self) -> u32 {
+        self.overflows
+    }
+
+    /// Number of bytes waiting to be parsed
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether there are no bytes waiting to be parsed
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The parser which processes the buffered bytes
+    pub fn parser(&self) -> &Parser {
+        &self.parser
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.buf[self.read];
+        self.read = (self.read + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, DiskSet};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn wrap_around() {
+        let mut ring = RingParser::<16>::new();
+        for _ in 0..5 {
+            assert_eq!(ring.write(&Response::Ack.to_bytes()), Ok(10));
+            assert_eq!(ring.len(), 10);
+            assert_eq!(ring.drain().collect::<Vec<_>>(), [Ok(Response::Ack)]);
+            assert!(ring.is_empty());
+        }
+        assert_eq!(ring.parser().stats().messages_parsed, 5);
+        assert_eq!(ring.overflow_count(), 0);
+    }
+
+    #[test]
+    fn packet_straddles_boundary() {
+        let mut ring = RingParser::<12>::new();
+        // move the pointers close to the end of the buffer
+        ring.write(&[0x00; 8]).unwrap();
+        assert_eq!(ring.drain().count(), 0);
+
+        let packet = Response::DiskInserted(Disk::Tf).to_bytes();
+        ring.write(&packet).unwrap();
+        // the write pointer has wrapped around past the read pointer
+        assert!(ring.write < ring.read);
+        assert_eq!(
+            ring.drain().collect::<Vec<_>>(),
+            [Ok(Response::DiskInserted(Disk::Tf))]
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut ring = RingParser::<16>::new();
+        let online = Response::DiskOnline(DiskSet::TF).to_bytes();
+        ring.write(&online).unwrap();
+        assert_eq!(
+            ring.write(&Response::Ack.to_bytes()),
+            Err(Error::BufferOverflow)
+        );
+        assert_eq!(ring.len(), 16);
+        assert_eq!(ring.write(&[0x7e]), Err(Error::BufferOverflow));
+        assert_eq!(ring.overflow_count(), 2);
+
+        // the truncated ACK is left incomplete in the parser
+        assert_eq!(
+            ring.drain().collect::<Vec<_>>(),
+            [Ok(Response::DiskOnline(DiskSet::TF))]
+        );
+        assert!(ring.is_empty());
+        assert_eq!(ring.write(&[]), Ok(0));
+
+        let mut empty = RingParser::<0>::new();
+        assert_eq!(empty.write(&[]), Ok(0));
+        assert_eq!(empty.write(&[0x7e]), Err(Error::BufferOverflow));
+        assert_eq!(empty.drain().count(), 0);
+    }
+
+    #[test]
+    fn drain_reports_errors() {
+        let mut ring = RingParser::<32>::new();
+        let mut bad = Response::Ack.to_bytes();
+        bad[8] ^= 0xff;
+        ring.write(&bad).unwrap();
+        ring.write(&Response::Ack.to_bytes()).unwrap();
+
+        let mut drain = ring.drain();
+        assert!(matches!(drain.next(), Some(Err(Error::BadChecksum { .. }))));
+        assert_eq!(drain.next(), Some(Ok(Response::Ack)));
+        assert_eq!(drain.next(), None);
+    }
+}
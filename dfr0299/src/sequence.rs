@@ -0,0 +1,325 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Scripted command sequences with a delay after each command.
+
+use crate::{Command, Error, ParseResult, Parser, Response, Result};
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+/// How long to wait before reading again when the reader has no data
+const READ_BACKOFF: Duration = Duration::from_millis(1);
+
+/// A list of commands to send in order, each followed by a delay to
+/// give the device time to act on it, e.g. a startup sequence.
+///
+/// ## Example
+/// ```no_run
+/// # fn a<W: std::io::Write>(mut uart: W) -> dfr0299::Result<()> {
+/// use dfr0299::{Command, CommandSequence, PlaybackSource};
+/// use std::time::Duration;
+/// let mut sequence = CommandSequence::new();
+/// sequence.push(Command::Reset, Duration::from_millis(500));
+/// sequence.push(
+///     Command::SetPlaybackSource(PlaybackSource::Tf),
+///     Duration::from_millis(200),
+/// );
+/// sequence.push(Command::SetVolume(20), Duration::from_millis(100));
+/// sequence.push(Command::Track(1), Duration::ZERO);
+/// sequence.execute(&mut uart)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CommandSequence {
+    steps: Vec<(Command, Duration)>,
+}
+
+impl CommandSequence {
+    /// Create an empty sequence
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a command to the end of the sequence, to be followed by the
+    /// given delay
+    pub fn push(&mut self, cmd: Command, delay: Duration) {
+        self.steps.push((cmd, delay));
+    }
+
+    /// Number of steps in the sequence
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether the sequence has no steps
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// The steps of the sequence in order
+    pub fn iter(&self) -> core::slice::Iter<'_, (Command, Duration)> {
+        self.steps.iter()
+    }
+
+    /// Serialise and write each command in turn, sleeping for its delay
+    /// after writing it. Returns `Error::IoWrite` if a write fails, in
+    /// which case the remaining commands are not sent.
+    pub fn execute<W: Write>(&self, writer: &mut W) -> Result<()> {
+        for (cmd, delay) in &self.steps {
+            write_command(writer, cmd)?;
+            std::thread::sleep(*delay);
+        }
+        Ok(())
+    }
+
+    /// Execute the sequence as with `CommandSequence::execute`, but
+    /// read from the port during each delay instead of sleeping. Each
+    /// response which arrives is passed to the callback along with the
+    /// command which was most recently sent. Packets which fail to
+    /// parse are skipped.
+    ///
+    /// Reads which return `ErrorKind::WouldBlock` or
+    /// `ErrorKind::TimedOut` are retried until the delay has elapsed,
+    /// and once the reader reaches the end of its input the rest of
+    /// the delay is slept. Any other read error is returned as
+    /// `Error::IoRead`.
+    pub fn execute_with_callback<P, F>(
+        &self,
+        port: &mut P,
+        mut callback: F,
+    ) -> Result<()>
+    where
+        P: Read + Write,
+        F: FnMut(&Command, &Response),
+    {
+        let mut parser = Parser::new();
+        let mut buf = [0u8; 32];
+        for (cmd, delay) in &self.steps {
+            write_command(port, cmd)?;
+            let deadline = Instant::now() + *delay;
+            while let Some(remaining) =
+                deadline.checked_duration_since(Instant::now())
+            {
+                if remaining.is_zero() {
+                    break;
+                }
+                match port.read(&mut buf) {
+                    Ok(0) => {
+                        std::thread::sleep(remaining);
+                        break;
+                    }
+                    Ok(len) => {
+                        for &byte in &buf[..len] {
+                            if let Ok(ParseResult::Complete(resp)) =
+                                parser.process_byte(byte)
+                            {
+                                callback(cmd, &resp);
+                            }
+                        }
+                    }
+                    Err(e)
+                        if matches!(
+                            e.kind(),
+                            ErrorKind::WouldBlock | ErrorKind::TimedOut
+                        ) =>
+                    {
+                        std::thread::sleep(remaining.min(READ_BACKOFF));
+                    }
+                    Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                    Err(_) => return Err(Error::IoRead),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_command<W: Write>(writer: &mut W, cmd: &Command) -> Result<()> {
+    writer
+        .write_all(&cmd.to_bytes())
+        .map_err(|_| Error::IoWrite)
+}
+
+impl IntoIterator for CommandSequence {
+    type Item = (Command, Duration);
+    type IntoIter = std::vec::IntoIter<(Command, Duration)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.steps.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a CommandSequence {
+    type Item = &'a (Command, Duration);
+    type IntoIter = core::slice::Iter<'a, (Command, Duration)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, DiskSet, PlaybackSource};
+    use pretty_assertions::assert_eq;
+    use std::collections::VecDeque;
+
+    fn startup() -> CommandSequence {
+        let mut sequence = CommandSequence::new();
+        sequence.push(Command::Reset, Duration::from_millis(20));
+        sequence.push(
+            Command::SetPlaybackSource(PlaybackSource::Tf),
+            Duration::from_millis(20),
+        );
+        sequence.push(Command::Track(1), Duration::ZERO);
+        sequence
+    }
+
+    #[test]
+    fn execute() {
+        let sequence = startup();
+        let mut written = Vec::new();
+        let start = Instant::now();
+        sequence.execute(&mut written).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        let expected: Vec<u8> = sequence
+            .iter()
+            .flat_map(|(cmd, _)| cmd.to_bytes())
+            .collect();
+        assert_eq!(written, expected);
+        assert_eq!(sequence.len(), 3);
+
+        let steps: Vec<_> = sequence.into_iter().collect();
+        assert_eq!(steps[0], (Command::Reset, Duration::from_millis(20)));
+        assert_eq!(steps[2], (Command::Track(1), Duration::ZERO));
+    }
+
+    #[test]
+    fn write_error() {
+        struct Broken;
+
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        assert_eq!(startup().execute(&mut Broken), Err(Error::IoWrite));
+    }
+
+    /// Replies to each write with the next queued bytes, and returns
+    /// `WouldBlock` when it has nothing to read
+    struct FakePort {
+        written: Vec<u8>,
+        replies: VecDeque<Vec<u8>>,
+        incoming: VecDeque<u8>,
+    }
+
+    impl Read for FakePort {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.incoming.is_empty() {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            // deliver a few bytes at a time
+            let len = buf.len().min(self.incoming.len()).min(3);
+            for (dst, src) in buf.iter_mut().zip(self.incoming.drain(..len)) {
+                *dst = src;
+            }
+            Ok(len)
+        }
+    }
+
+    impl Write for FakePort {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            // each packet is written in a single call
+            self.written.extend_from_slice(buf);
+            if let Some(reply) = self.replies.pop_front() {
+                self.incoming.extend(reply);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn execute_with_callback() {
+        let mut noisy_ack = vec![0x00, 0x7e];
+        noisy_ack.extend(Response::Ack.to_bytes());
+        let mut port = FakePort {
+            written: Vec::new(),
+            replies: [
+                [
+                    Response::DiskOnline(DiskSet::TF).to_bytes(),
+                    Response::Ack.to_bytes(),
+                ]
+                .concat(),
+                noisy_ack,
+                Response::DiskInserted(Disk::UDisk).to_bytes().to_vec(),
+            ]
+            .into(),
+            incoming: VecDeque::new(),
+        };
+
+        let mut received = Vec::new();
+        startup()
+            .execute_with_callback(&mut port, |cmd, resp| {
+                received.push((*cmd, *resp))
+            })
+            .unwrap();
+
+        // the last step has no delay so its reply is never read
+        assert_eq!(
+            received,
+            [
+                (Command::Reset, Response::DiskOnline(DiskSet::TF)),
+                (Command::Reset, Response::Ack),
+                (
+                    Command::SetPlaybackSource(PlaybackSource::Tf),
+                    Response::Ack
+                ),
+            ]
+        );
+        assert_eq!(port.written.len(), 30);
+    }
+
+    #[test]
+    fn read_error() {
+        struct BrokenReader(Vec<u8>);
+
+        impl Read for BrokenReader {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        impl Write for BrokenReader {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut port = BrokenReader(Vec::new());
+        assert_eq!(
+            startup().execute_with_callback(&mut port, |_, _| {}),
+            Err(Error::IoRead)
+        );
+        // the first command was written before the read failed
+        assert_eq!(port.0, Command::Reset.to_bytes());
+    }
+}
@@ -0,0 +1,273 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `futures`, for consuming responses from an
+//! `AsyncRead` as a `Stream`, or pushing bytes into a parser as a
+//! `Sink`.
+
+use crate::{Error, ParseResult, Parser, Response, Result};
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+use futures::channel::mpsc::Sender;
+use futures::io::AsyncRead;
+use futures::{Sink, Stream};
+
+/// Wrapper around an `AsyncRead` which yields each response parsed
+/// from it. Parse errors are yielded as they occur and the stream
+/// continues afterwards, while an error from the reader is yielded as
+/// `Error::IoRead`. The stream ends when the reader reaches the end of
+/// its input.
+///
+/// ## Example
+/// ```no_run
+/// # async fn a<R: futures::io::AsyncRead + Unpin>(socket: R) {
+/// use dfr0299::stream::StreamParser;
+/// use futures::StreamExt;
+/// let mut stream = StreamParser::new(socket);
+/// while let Some(resp) = stream.next().await {
+///     println!("{resp:?}");
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct StreamParser<R> {
+    reader: R,
+    parser: Parser,
+    buf: [u8; 16],
+    pos: usize,
+    len: usize,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> StreamParser<R> {
+    /// Wrap a reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            parser: Parser::new(),
+            buf: [0; 16],
+            pos: 0,
+            len: 0,
+            done: false,
+        }
+    }
+
+    /// Release the underlying reader. Any bytes which have been read
+    /// but not yet parsed are discarded.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for StreamParser<R> {
+    type Item = Result<Response>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            while let Some(&byte) = this.buf[..this.len].get(this.pos) {
+                this.pos += 1;
+                match this.parser.process_byte(byte) {
+                    Ok(ParseResult::Complete(resp)) => {
+                        return Poll::Ready(Some(Ok(resp)))
+                    }
+                    Ok(_) => {}
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            let read = Pin::new(&mut this.reader).poll_read(cx, &mut this.buf);
+            match ready!(read) {
+                Ok(0) => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                Ok(len) => {
+                    this.pos = 0;
+                    this.len = len;
+                }
+                Err(_) => return Poll::Ready(Some(Err(Error::IoRead))),
+            }
+        }
+    }
+}
+
+/// `Sink` which parses each byte sent to it, and sends the complete
+/// responses and parse errors to a channel. Closing the sink closes the
+/// channel. If the receiving end of the channel has been dropped then
+/// the sink returns `Error::IoWrite`.
+///
+/// ## Example
+/// ```no_run
+/// # async fn a(bytes: Vec<u8>) -> dfr0299::Result<()> {
+/// use dfr0299::stream::ParserSink;
+/// use futures::channel::mpsc;
+/// use futures::{SinkExt, StreamExt};
+/// let (tx, mut rx) = mpsc::channel(4);
+/// let mut sink = ParserSink::new(tx);
+/// sink.send_all(&mut futures::stream::iter(bytes).map(Ok)).await?;
+/// while let Some(resp) = rx.next().await {
+///     println!("{resp:?}");
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ParserSink {
+    inner: Parser,
+    tx: Sender<Result<Response>>,
+}
+
+impl ParserSink {
+    /// Create a sink which sends its responses to `tx`
+    pub fn new(tx: Sender<Result<Response>>) -> Self {
+        Self {
+            inner: Parser::new(),
+            tx,
+        }
+    }
+
+    /// The parser used by the sink, e.g. to check its stats
+    pub fn parser(&self) -> &Parser {
+        &self.inner
+    }
+}
+
+impl Sink<u8> for ParserSink {
+    type Error = Error;
+
+    /// Ready once the channel has room for a response, since any byte
+    /// might complete one
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<()>> {
+        self.get_mut().tx.poll_ready(cx).map_err(|_| Error::IoWrite)
+    }
+
+    fn start_send(self: Pin<&mut Self>, byte: u8) -> Result<()> {
+        let this = self.get_mut();
+        let item = match this.inner.process_byte(byte) {
+            Ok(ParseResult::Complete(resp)) => Ok(resp),
+            Ok(_) => return Ok(()),
+            Err(e) => Err(e),
+        };
+        this.tx.start_send(item).map_err(|_| Error::IoWrite)
+    }
+
+    /// Flush the responses into the channel. A partially received
+    /// packet remains in the parser, as it can't be completed until
+    /// more bytes arrive.
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().tx)
+            .poll_flush(cx)
+            .map_err(|_| Error::IoWrite)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<()>> {
+        Pin::new(&mut self.get_mut().tx)
+            .poll_close(cx)
+            .map_err(|_| Error::IoWrite)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, DiskSet};
+    use futures::channel::mpsc;
+    use futures::executor::block_on;
+    use futures::io::Cursor;
+    use futures::{SinkExt, StreamExt};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn stream_responses() {
+        let data = [
+            0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef, //
+            0x00, 0x01, // noise
+            0x7e, 0xff, 0x06, 0x3f, 0x00, 0x00, 0x02, 0xfe, 0xba, 0xef, //
+            0x7e, 0xff, 0x06, 0x3f, 0x00, 0x00, 0x02, 0xfe, 0x00, 0xef, //
+            0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x02, 0xfe, 0xbf, 0xef,
+        ];
+        let stream = StreamParser::new(Cursor::new(data));
+        let responses: Vec<_> = block_on(stream.collect());
+        assert_eq!(
+            responses,
+            [
+                Ok(Response::DiskRemoved(Disk::UDisk)),
+                Ok(Response::DiskOnline(DiskSet::TF)),
+                Err(Error::BadChecksum {
+                    expected: [0xfe, 0xba],
+                    received: [0xfe, 0x00],
+                }),
+                Ok(Response::DiskInserted(Disk::Tf)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sink_responses() {
+        let data = [
+            0x7e, 0xff, 0x06, 0x3b, 0x00, 0x00, 0x01, 0xfe, 0xbf, 0xef, //
+            0x7e, 0xff, 0x06, 0x3f, 0x00, 0x00, 0x02, 0xfe, 0x00, 0xef, //
+            0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x02, 0xfe, 0xbf, 0xef, //
+            0x7e, 0xff, 0x06, // partial packet
+        ];
+        let (tx, rx) = mpsc::channel(1);
+        let mut sink = ParserSink::new(tx);
+        let (sent, responses) = block_on(async {
+            let send = async {
+                for byte in data {
+                    sink.feed(byte).await?;
+                }
+                // flushing leaves the partial packet in the parser
+                sink.flush().await?;
+                let received = sink.parser().stats().bytes_received;
+                sink.close().await?;
+                Ok::<_, Error>(received)
+            };
+            futures::future::join(send, rx.collect::<Vec<_>>()).await
+        });
+        assert_eq!(sent, Ok(data.len() as u32));
+        assert_eq!(
+            responses,
+            [
+                Ok(Response::DiskRemoved(Disk::UDisk)),
+                Err(Error::BadChecksum {
+                    expected: [0xfe, 0xba],
+                    received: [0xfe, 0x00],
+                }),
+                Ok(Response::DiskInserted(Disk::Tf)),
+            ]
+        );
+    }
+
+    #[test]
+    fn sink_receiver_dropped() {
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+        let mut sink = ParserSink::new(tx);
+        assert_eq!(block_on(sink.send(0x7e)), Err(Error::IoWrite));
+    }
+
+    #[test]
+    fn stream_ends_with_reader() {
+        let mut stream = StreamParser::new(Cursor::new([0x7e, 0xff, 0x06]));
+        assert_eq!(block_on(stream.next()), None);
+        assert_eq!(block_on(stream.next()), None);
+    }
+}
@@ -0,0 +1,128 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Helpers for testing code which talks to the device, including on
+//! embedded targets where the standard test assertions aren't
+//! available.
+
+use crate::{Command, RequestAck, Response};
+
+/// Construct the packet the device would send for a response, e.g. for
+/// injecting into a `MockSerial` with `MockSerial::inject_raw`
+pub fn make_response_packet(resp: &Response) -> [u8; 10] {
+    resp.to_bytes()
+}
+
+/// Construct the packet the host would send for a command
+pub fn make_command_packet(
+    cmd: &Command,
+    ack: impl Into<RequestAck>,
+) -> [u8; 10] {
+    cmd.to_bytes_with_ack(ack)
+}
+
+/// An expected response which received responses can be checked
+/// against. With the `use_defmt` feature the assertion is made with
+/// `defmt::assert_eq!`, so that failures are reported over the defmt
+/// transport.
+///
+/// ## Example
+/// ```
+/// use dfr0299::{test_utils::ResponseMatcher, DiskSet, Response};
+/// let matcher = ResponseMatcher::new(Response::Ack);
+/// assert!(matcher.matches(&Response::Ack));
+/// assert!(!matcher.matches(&Response::DiskOnline(DiskSet::TF)));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "use_defmt", derive(defmt::Format))]
+pub struct ResponseMatcher {
+    expected: Response,
+}
+
+impl ResponseMatcher {
+    /// Create a matcher for the given response
+    pub fn new(expected: Response) -> Self {
+        Self { expected }
+    }
+
+    /// The expected response
+    pub fn expected(&self) -> &Response {
+        &self.expected
+    }
+
+    /// Whether a received response is the expected one
+    pub fn matches(&self, actual: &Response) -> bool {
+        *actual == self.expected
+    }
+
+    /// Panic if a received response isn't the expected one
+    #[track_caller]
+    pub fn assert_matches(&self, actual: &Response) {
+        // this crate's own tests run on the host, without a defmt logger
+        #[cfg(all(feature = "use_defmt", not(test)))]
+        defmt::assert_eq!(*actual, self.expected);
+        #[cfg(not(all(feature = "use_defmt", not(test))))]
+        assert_eq!(*actual, self.expected);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, DiskSet, ModuleErrorType, ParseResult, Parser, Storage};
+    use pretty_assertions::assert_eq;
+
+    const RESPONSES: [Response; 8] = [
+        Response::Ack,
+        Response::DiskOnline(DiskSet::TF),
+        Response::UDiskFinishPlayback(1),
+        Response::TfFinishPlayback(0x1234),
+        Response::FlashFinishPlayback(2999),
+        Response::ModuleError(ModuleErrorType::ChecksumError),
+        Response::DiskInserted(Disk::UDisk),
+        Response::DiskRemoved(Disk::Flash),
+    ];
+
+    #[test]
+    fn response_packets_parse() {
+        let mut parser = Parser::new();
+        for resp in RESPONSES {
+            let packet = make_response_packet(&resp);
+            let mut results = packet.map(|byte| parser.process_byte(byte));
+            assert_eq!(results[9], Ok(ParseResult::Complete(resp)));
+            results[9] = Ok(ParseResult::Incomplete);
+            assert!(results.iter().all(|r| *r == Ok(ParseResult::Incomplete)));
+        }
+        assert_eq!(parser.stats().checksum_errors, 0);
+    }
+
+    #[test]
+    fn command_packets() {
+        let cmd = Command::GetFileCount(Storage::Flash);
+        assert_eq!(
+            make_command_packet(&cmd, RequestAck::Yes),
+            cmd.to_bytes_with_ack(RequestAck::Yes)
+        );
+        assert_eq!(
+            Command::try_from_bytes(&make_command_packet(&cmd, false)),
+            Ok((cmd, RequestAck::No))
+        );
+    }
+
+    #[test]
+    fn response_matcher() {
+        let matcher = ResponseMatcher::new(Response::DiskInserted(Disk::Tf));
+        assert_eq!(matcher.expected(), &Response::DiskInserted(Disk::Tf));
+        assert!(matcher.matches(&Response::DiskInserted(Disk::Tf)));
+        assert!(!matcher.matches(&Response::DiskRemoved(Disk::Tf)));
+        matcher.assert_matches(&Response::DiskInserted(Disk::Tf));
+    }
+
+    #[test]
+    #[should_panic]
+    fn response_matcher_mismatch() {
+        ResponseMatcher::new(Response::Ack)
+            .assert_matches(&Response::DiskRemoved(Disk::Tf));
+    }
+}
@@ -0,0 +1,230 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Integration with `tokio`, for talking to the device via
+//! `tokio_serial` from an async service.
+
+use crate::{
+    Command, Error, ParseResult, Parser, RequestAck, Response, Result,
+};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Async connection to the device. This is usually a
+/// `tokio_serial::SerialStream` opened with `TokioSerial::new`, but any
+/// `AsyncRead + AsyncWrite` stream can be wrapped with
+/// `TokioSerial::from_stream`.
+///
+/// ## Example
+/// ```no_run
+/// # async fn a() -> dfr0299::Result<()> {
+/// use dfr0299::{tokio_io::TokioSerial, Command};
+/// use std::time::Duration;
+/// let mut serial = TokioSerial::new("/dev/ttyUSB0", 9600)?;
+/// serial
+///     .send_with_ack(Command::Track(1), Duration::from_millis(200))
+///     .await?;
+/// loop {
+///     println!("{:?}", serial.recv().await?);
+/// }
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct TokioSerial<S = SerialStream> {
+    stream: S,
+    parser: Parser,
+    buf: [u8; 16],
+    pos: usize,
+    len: usize,
+}
+
+impl TokioSerial<SerialStream> {
+    /// Open the serial port at `path`. The device uses a baud rate of
+    /// 9600. Returns `Error::SerialOpen` if the port can't be opened.
+    pub fn new(path: &str, baud: u32) -> Result<Self> {
+        let stream = tokio_serial::new(path, baud)
+            .open_native_async()
+            .map_err(|_| Error::SerialOpen)?;
+        Ok(Self::from_stream(stream))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> TokioSerial<S> {
+    /// Wrap an already opened stream
+    pub fn from_stream(stream: S) -> Self {
+        Self {
+            stream,
+            parser: Parser::new(),
+            buf: [0; 16],
+            pos: 0,
+            len: 0,
+        }
+    }
+
+    /// Release the underlying stream. Any bytes which have been read
+    /// but not yet parsed are discarded.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+
+    /// Send a command without requesting an ACK
+    pub async fn send(&mut self, cmd: Command) -> Result<()> {
+        self.send_raw(cmd, RequestAck::No).await
+    }
+
+    /// Send a command with `RequestAck::Yes`, then wait for up to
+    /// `timeout` for the device's `Response::Ack`. Other responses and
+    /// packets which fail to parse are discarded in the meantime.
+    /// Returns `Error::Timeout` if no ACK arrives in time, or
+    /// `Error::IoRead` if the stream fails first.
+    pub async fn send_with_ack(
+        &mut self,
+        cmd: Command,
+        timeout: Duration,
+    ) -> Result<()> {
+        self.send_raw(cmd, RequestAck::Yes).await?;
+        tokio::time::timeout(timeout, async {
+            loop {
+                match self.recv().await {
+                    Ok(Response::Ack) => return Ok(()),
+                    Err(Error::IoRead) => return Err(Error::IoRead),
+                    Ok(_) | Err(_) => {}
+                }
+            }
+        })
+        .await
+        .map_err(|_| Error::Timeout)?
+    }
+
+    /// Wait for the next complete response. Returns `Error::IoRead` if
+    /// the stream returns an error or is closed.
+    pub async fn recv(&mut self) -> Result<Response> {
+        loop {
+            while let Some(&byte) = self.buf[..self.len].get(self.pos) {
+                self.pos += 1;
+                if let ParseResult::Complete(resp) =
+                    self.parser.process_byte(byte)?
+                {
+                    return Ok(resp);
+                }
+            }
+
+            let len = self
+                .stream
+                .read(&mut self.buf)
+                .await
+                .map_err(|_| Error::IoRead)?;
+            if len == 0 {
+                return Err(Error::IoRead);
+            }
+            self.pos = 0;
+            self.len = len;
+        }
+    }
+
+    async fn send_raw(
+        &mut self,
+        cmd: Command,
+        request_ack: RequestAck,
+    ) -> Result<()> {
+        let mut buf = [0u8; 10];
+        cmd.serialise_with_ack(&mut buf, request_ack)?;
+        self.stream
+            .write_all(&buf)
+            .await
+            .map_err(|_| Error::IoWrite)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Disk;
+    use pretty_assertions::assert_eq;
+    use tokio::io::DuplexStream;
+
+    const ACK: [u8; 10] =
+        [0x7e, 0xff, 0x06, 0x41, 0x00, 0x00, 0x00, 0xfe, 0xba, 0xef];
+    const TF_INSERT: [u8; 10] =
+        [0x7e, 0xff, 0x06, 0x3a, 0x00, 0x00, 0x02, 0xfe, 0xbf, 0xef];
+
+    async fn read_command(device: &mut DuplexStream) -> (Command, RequestAck) {
+        let mut buf = [0u8; 10];
+        device.read_exact(&mut buf).await.unwrap();
+        Command::try_from_bytes(&buf).unwrap()
+    }
+
+    #[tokio::test]
+    async fn send_and_recv() {
+        let (host, mut device) = tokio::io::duplex(64);
+        let mut serial = TokioSerial::from_stream(host);
+
+        serial.send(Command::Next).await.unwrap();
+        assert_eq!(
+            read_command(&mut device).await,
+            (Command::Next, RequestAck::No)
+        );
+
+        device.write_all(&TF_INSERT).await.unwrap();
+        assert_eq!(serial.recv().await, Ok(Response::DiskInserted(Disk::Tf)));
+
+        drop(device);
+        assert_eq!(serial.recv().await, Err(Error::IoRead));
+    }
+
+    #[tokio::test]
+    async fn send_with_ack() {
+        let (host, mut device) = tokio::io::duplex(64);
+        let mut serial = TokioSerial::from_stream(host);
+
+        let device = tokio::spawn(async move {
+            let cmd = read_command(&mut device).await;
+            device.write_all(&TF_INSERT).await.unwrap();
+            device.write_all(&ACK).await.unwrap();
+            (cmd, device)
+        });
+        serial
+            .send_with_ack(Command::Track(3), Duration::from_secs(5))
+            .await
+            .unwrap();
+        let (cmd, mut device) = device.await.unwrap();
+        assert_eq!(cmd, (Command::Track(3), RequestAck::Yes));
+
+        // corrupt packets and unknown replies before the ACK are skipped
+        let mut corrupt = TF_INSERT;
+        corrupt[8] ^= 0xff;
+        let mut unknown =
+            [0x7e, 0xff, 0x06, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0xef];
+        unknown[7..9].copy_from_slice(
+            &crate::calculate_checksum(0xff, 0x06, 0x30, 0x00, 0x00, 0x00)
+                .to_be_bytes(),
+        );
+        let device = tokio::spawn(async move {
+            let cmd = read_command(&mut device).await;
+            device.write_all(&corrupt).await.unwrap();
+            device.write_all(&unknown).await.unwrap();
+            device.write_all(&ACK).await.unwrap();
+            (cmd, device)
+        });
+        serial
+            .send_with_ack(Command::Next, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let (cmd, mut device) = device.await.unwrap();
+        assert_eq!(cmd, (Command::Next, RequestAck::Yes));
+
+        // no ACK this time
+        assert_eq!(
+            serial
+                .send_with_ack(Command::Pause, Duration::from_millis(10))
+                .await,
+            Err(Error::Timeout)
+        );
+        assert_eq!(
+            read_command(&mut device).await,
+            (Command::Pause, RequestAck::Yes)
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Correlation of query commands with the device's replies.
+
+use crate::proto::IDX_CMD;
+use crate::{Command, Error, Response, Result};
+
+/// Tracks outstanding query commands so that replies can be matched up
+/// with the query that requested them. The device doesn't label its
+/// replies, but it does answer queries in the order that they were
+/// sent, using the command byte of the query.
+///
+/// None of the `Response` variants are query replies, so a parser
+/// created with `Parser::new_permissive` reports them as
+/// `ParseResult::Ignored(cmd)`; pass that byte to
+/// `QueryTracker::match_command_byte`.
+///
+/// ## Example
+/// ```
+/// use dfr0299::{Command, QueryTracker};
+/// let mut tracker = QueryTracker::<4>::new();
+/// tracker.expect(Command::GetVolume)?;
+/// tracker.expect(Command::GetEq)?;
+/// assert_eq!(tracker.match_command_byte(0x43), Some(Command::GetVolume));
+/// assert_eq!(tracker.match_command_byte(0x44), Some(Command::GetEq));
+/// assert_eq!(tracker.pending_count(), 0);
+/// # Ok::<(), dfr0299::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct QueryTracker<const N: usize> {
+    pending: heapless::Deque<Command, N>,
+}
+
+impl<const N: usize> QueryTracker<N> {
+    /// Create a tracker with no pending queries
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that a query has been sent to the device. Returns
+    /// `Error::NotAQuery` if the command isn't a query, or
+    /// `Error::QueueFull` if `N` queries are already pending.
+    pub fn expect(&mut self, cmd: Command) -> Result<()> {
+        if !cmd.is_query() {
+            return Err(Error::NotAQuery(cmd.command_byte()));
+        }
+        self.pending.push_back(cmd).map_err(|_| Error::QueueFull)
+    }
+
+    /// Match a parsed response against the oldest pending query,
+    /// removing the query and returning it if the response is its
+    /// reply. Unsolicited responses such as `Response::DiskInserted`
+    /// leave the pending queries untouched.
+    pub fn match_response(&mut self, resp: Response) -> Option<Command> {
+        self.match_command_byte(resp.to_bytes()[IDX_CMD])
+    }
+
+    /// Match the command byte of a received packet against the oldest
+    /// pending query, as with `QueryTracker::match_response`
+    pub fn match_command_byte(&mut self, cmd: u8) -> Option<Command> {
+        let oldest = self.pending.front()?;
+        if oldest.expected_response_cmd() != Some(cmd) {
+            return None;
+        }
+        self.pending.pop_front()
+    }
+
+    /// Number of queries which are still waiting for a reply
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Forget all pending queries, e.g. after the device has been reset
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Disk, ModuleErrorType, Storage};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn interleaved_queries() {
+        use Command::*;
+        let mut tracker = QueryTracker::<4>::new();
+        tracker.expect(GetVolume).unwrap();
+        tracker.expect(GetEq).unwrap();
+        assert_eq!(tracker.pending_count(), 2);
+
+        // unsolicited responses arrive between the replies
+        assert_eq!(
+            tracker.match_response(Response::DiskInserted(Disk::Tf)),
+            None
+        );
+        assert_eq!(tracker.match_command_byte(0x43), Some(GetVolume));
+        tracker.expect(GetFileCount(Storage::Tf)).unwrap();
+        assert_eq!(
+            tracker
+                .match_response(Response::ModuleError(ModuleErrorType::Busy)),
+            None
+        );
+        // replies out of order don't match
+        assert_eq!(tracker.match_command_byte(0x47), None);
+        assert_eq!(tracker.match_command_byte(0x44), Some(GetEq));
+        assert_eq!(
+            tracker.match_command_byte(0x47),
+            Some(GetFileCount(Storage::Tf))
+        );
+        assert_eq!(tracker.pending_count(), 0);
+        assert_eq!(tracker.match_command_byte(0x43), None);
+    }
+
+    #[test]
+    fn expect_errors() {
+        let mut tracker = QueryTracker::<1>::new();
+        assert_eq!(tracker.expect(Command::Next), Err(Error::NotAQuery(0x01)));
+        assert_eq!(
+            tracker.expect(Command::SetVolume(3)),
+            Err(Error::NotAQuery(0x06))
+        );
+        tracker.expect(Command::GetStatus).unwrap();
+        assert_eq!(tracker.expect(Command::GetStatus), Err(Error::QueueFull));
+        assert_eq!(tracker.pending_count(), 1);
+        tracker.clear();
+        assert_eq!(tracker.pending_count(), 0);
+    }
+}
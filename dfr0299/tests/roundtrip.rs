@@ -0,0 +1,82 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Property tests that serialised commands survive a trip through the
+//! parsers. Run with `cargo test --features use_arbitrary`.
+
+#![cfg(feature = "use_arbitrary")]
+
+use arbitrary::{Arbitrary, Unstructured};
+use dfr0299::{Command, Error, ParseResult, Parser, RequestAck, Response};
+use proptest::prelude::*;
+
+/// Arbitrary commands generated from the `arbitrary` derive, since the
+/// crate doesn't depend on proptest itself
+fn command() -> impl Strategy<Value = Command> {
+    prop::collection::vec(any::<u8>(), 32)
+        .prop_filter_map("not enough data for a command", |data| {
+            Command::arbitrary(&mut Unstructured::new(&data)).ok()
+        })
+}
+
+fn ack() -> impl Strategy<Value = RequestAck> {
+    prop_oneof![Just(RequestAck::No), Just(RequestAck::Yes)]
+}
+
+proptest! {
+    #[test]
+    fn command_bytes_round_trip(cmd in command(), ack in ack()) {
+        let packet = cmd.to_bytes_with_ack(ack);
+        match Command::try_from_bytes(&packet) {
+            Ok((parsed, parsed_ack)) => {
+                prop_assert_eq!(parsed_ack, ack);
+                prop_assert_eq!(parsed.command_byte(), cmd.command_byte());
+                // commands which take no parameter ignore it, so only
+                // the documented variants round trip exactly
+                match cmd {
+                    Command::Custom { .. } => {}
+                    // parsed as `AdvertiseFolder` with the same packet
+                    Command::SetFolderTrack(_) => {
+                        prop_assert_eq!(parsed.to_bytes_with_ack(ack), packet);
+                    }
+                    _ => {
+                        prop_assert_eq!(parsed, cmd);
+                        prop_assert_eq!(parsed.to_bytes_with_ack(ack), packet);
+                    }
+                }
+            }
+            // custom commands may not correspond to a known command
+            Err(Error::InvalidCommand(_) | Error::InvalidParameterValue(_))
+                if matches!(cmd, Command::Custom { .. }) => {}
+            Err(e) => prop_assert!(false, "{:?} failed to parse: {}", cmd, e),
+        }
+    }
+
+    #[test]
+    fn parser_accepts_every_packet(cmd in command(), ack in ack()) {
+        let packet = cmd.to_bytes_with_ack(ack);
+        let mut parser = Parser::new_permissive();
+        for byte in &packet[..9] {
+            prop_assert_eq!(
+                parser.process_byte(*byte),
+                Ok(ParseResult::Incomplete)
+            );
+        }
+
+        // the framing and checksum are always valid, although most
+        // command bytes aren't response codes
+        match parser.process_byte(packet[9]) {
+            Ok(ParseResult::Complete(resp)) => {
+                let encoded = resp.to_bytes();
+                prop_assert_eq!(encoded[3], packet[3]);
+                prop_assert_eq!(Response::try_from(encoded), Ok(resp));
+            }
+            Ok(ParseResult::Ignored(byte)) => prop_assert_eq!(byte, packet[3]),
+            Err(Error::InvalidParameterValue(_)) => {}
+            other => prop_assert!(false, "{:02x?} gave {:?}", packet, other),
+        }
+        prop_assert_eq!(parser.stats().checksum_errors, 0);
+        prop_assert_eq!(parser.stats().framing_errors, 0);
+    }
+}
@@ -0,0 +1,57 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, you can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Receive responses in a `mio` event loop rather than a read thread
+
+use color_eyre::Result;
+use dfr0299::{mio_io::MioParser, Command, Disk, DiskSet, Response};
+use mio::{Events, Interest, Poll, Token};
+
+const USAGE: &str = "Usage: ./event-loop PORT\n\
+    e.g. ./event-loop /dev/ttyUSB0";
+
+const SERIAL: Token = Token(0);
+
+fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    let port = std::env::args().nth(1).expect(USAGE);
+
+    let mut poll = Poll::new()?;
+    let mut serial = MioParser::new(&port, 9600)?;
+    poll.registry()
+        .register(&mut serial, SERIAL, Interest::READABLE)?;
+
+    serial.send(Command::Reset)?;
+
+    let mut events = Events::with_capacity(4);
+    loop {
+        poll.poll(&mut events, None)?;
+        for event in &events {
+            if event.token() != SERIAL {
+                continue;
+            }
+            // events are edge-triggered, so read everything available
+            while let Some(resp) = serial.try_recv_response() {
+                match resp {
+                    Ok(resp) => handle(&mut serial, resp)?,
+                    Err(e) => println!("Error: {e}"),
+                }
+            }
+        }
+    }
+}
+
+fn handle(serial: &mut MioParser, resp: Response) -> Result<()> {
+    println!("Received: {resp:?}");
+    match resp {
+        Response::DiskOnline(disks) if disks.contains(DiskSet::TF) => {
+            serial.send(Command::SetVolume(30))?;
+            serial.send(Command::Track(1))?;
+        }
+        Response::DiskInserted(Disk::Tf) => serial.send(Command::Track(1))?,
+        _ => {}
+    }
+    Ok(())
+}
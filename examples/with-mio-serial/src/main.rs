@@ -5,7 +5,7 @@
 use color_eyre::Result;
 use dfr0299::{
     Command::{self, *},
-    Disk, ParseResult, Parser, RequestAck,
+    Disk, DiskSet, ParseResult, Parser, RequestAck,
     Response::{self, *},
 };
 use mio_serial::SerialPort;
@@ -32,7 +32,7 @@ fn main() -> Result<()> {
 
     loop {
         let resp = rx.recv()?;
-        if resp == DiskOnline(Disk::Tf) {
+        if matches!(resp, DiskOnline(disks) if disks.contains(DiskSet::TF)) {
             println!("SD online");
             break;
         }
@@ -47,7 +47,10 @@ fn main() -> Result<()> {
     loop {
         let resp = rx.recv()?;
         match resp {
-            DiskInserted(Disk::Tf) | DiskOnline(Disk::Tf) => {
+            DiskInserted(Disk::Tf) => {
+                send(&mut port, Track(1))?;
+            }
+            DiskOnline(disks) if disks.contains(DiskSet::TF) => {
                 send(&mut port, Track(1))?;
             }
             _ => {}
@@ -86,6 +89,9 @@ fn read_loop(
                 // println!("Received: {:02x}", buf[0]);
                 match parser.process_byte(buf[0]) {
                     Ok(ParseResult::Incomplete) => {}
+                    Ok(ParseResult::Ignored(byte)) => {
+                        println!("Ignored unexpected byte {byte:#04x}");
+                    }
                     Ok(ParseResult::Complete(msg)) => {
                         println!("Received: {msg:?}");
                         tx.send(msg)?;